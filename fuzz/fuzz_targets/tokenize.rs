@@ -0,0 +1,28 @@
+// cargo-fuzz target for the inline/RESP request tokenizer in
+// `src/redis/line.rs`. Feeds arbitrary bytes through both entry points
+// and asserts only that neither panics or hangs — a malformed buffer
+// should always come back as `Incomplete`/`Invalid`, never a crash. The
+// RESP path's `take!(size)` in `bulk_string` already bounds every read
+// to the declared bulk length, so a huge `$<len>\r\n` just reports
+// `Incomplete` instead of reading past `data`; `parse_multibulk`'s own
+// declared `*<argc>` is bounded by `bounded_argc` before it ever reaches
+// `count!`, whose `Vec::with_capacity(argc)` would otherwise panic on an
+// absurd-but-still-`usize` count.
+//
+// This crate currently has no `Cargo.toml` of its own (see the workspace
+// root), so there's nothing for `fuzz/Cargo.toml` to depend on yet;
+// wiring this target into `cargo fuzz run tokenize` is left for once the
+// crate has a buildable manifest and exposes `redis::line` from a
+// library target.
+#![no_main]
+
+#[macro_use]
+extern crate libfuzzer_sys;
+extern crate redis;
+
+use redis::line::{parse_command, tokenize_request};
+
+fuzz_target!(|data: &[u8]| {
+    let _ = parse_command(data);
+    let _ = tokenize_request(data);
+});
@@ -0,0 +1,207 @@
+use mioco::tcp::TcpStream;
+use nom::IResult;
+use redis::commands::Command;
+use redis::resp::{decode, Value};
+use std::io::{self, Read, Write};
+use std::net::SocketAddr;
+
+const BUFFER_SIZE: usize = 1024 * 16;
+
+/// Blocking request/response access to a connection: `send` writes a
+/// command and blocks until exactly one reply has been read and decoded.
+pub trait SyncClient {
+    fn send(&mut self, cmd: Command) -> io::Result<Value>;
+}
+
+/// Pipelined access to a connection: `send_nowait` writes a command without
+/// waiting for its reply, so a batch of requests can be fired back-to-back;
+/// `drain` later blocks until `n` replies have arrived and decodes them in
+/// the order they were sent.
+pub trait AsyncClient {
+    fn send_nowait(&mut self, cmd: Command) -> io::Result<()>;
+    fn drain(&mut self, n: usize) -> io::Result<Vec<Value>>;
+}
+
+pub type TcpClient = Client<TcpStream>;
+
+impl TcpClient {
+    pub fn connect(address: &SocketAddr) -> io::Result<TcpClient> {
+        TcpStream::connect(address).map(Client::new)
+    }
+}
+
+/// A `SyncClient`/`AsyncClient` over any duplex byte stream. Replies are
+/// decoded out of a growable buffer the same way `handle_client` parses
+/// requests: read whatever is available, try to decode, and only block on
+/// another `read` when the buffered bytes don't yet hold a full reply.
+pub struct Client<S> {
+    stream: S,
+    buffer: Vec<u8>,
+    consumed: usize,
+}
+
+impl<S: Read + Write> Client<S> {
+    pub fn new(stream: S) -> Client<S> {
+        Client {
+            stream: stream,
+            buffer: Vec::new(),
+            consumed: 0,
+        }
+    }
+
+    fn write_command(&mut self, cmd: Command) -> io::Result<()> {
+        try!(self.stream.write_all(&cmd.to_resp()));
+        self.stream.flush()
+    }
+
+    // Scans the buffer for `n` complete replies, reading more bytes only
+    // when what's buffered isn't enough, then decodes all `n` at once.
+    // Decoding happens in a single pass over the final buffer so none of
+    // the borrowed `Value`s are invalidated by a later `read`.
+    fn read_values(&mut self, n: usize) -> io::Result<Vec<Value>> {
+        self.buffer.drain(..self.consumed);
+        self.consumed = 0;
+
+        let mut chunk = [0; BUFFER_SIZE];
+        let mut ends = Vec::with_capacity(n);
+        let mut scanned = 0;
+
+        while ends.len() < n {
+            match decode(&self.buffer[scanned..]) {
+                IResult::Done(rest, _) => {
+                    scanned = self.buffer.len() - rest.len();
+                    ends.push(scanned);
+                }
+                IResult::Incomplete(_) => {
+                    let read = try!(self.stream.read(&mut chunk));
+
+                    if read == 0 {
+                        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "connection closed"));
+                    }
+
+                    self.buffer.extend_from_slice(&chunk[..read]);
+                }
+                IResult::Error(_) =>
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, "protocol error")),
+            }
+        }
+
+        self.consumed = scanned;
+
+        let mut values = Vec::with_capacity(n);
+        let mut start = 0;
+
+        for end in ends {
+            match decode(&self.buffer[start..end]) {
+                IResult::Done(_, value) => values.push(value),
+                _ => unreachable!(),
+            }
+
+            start = end;
+        }
+
+        Ok(values)
+    }
+}
+
+impl<S: Read + Write> SyncClient for Client<S> {
+    fn send(&mut self, cmd: Command) -> io::Result<Value> {
+        try!(self.write_command(cmd));
+        self.read_values(1).map(|mut values| values.remove(0))
+    }
+}
+
+impl<S: Read + Write> AsyncClient for Client<S> {
+    fn send_nowait(&mut self, cmd: Command) -> io::Result<()> {
+        self.write_command(cmd)
+    }
+
+    fn drain(&mut self, n: usize) -> io::Result<Vec<Value>> {
+        self.read_values(n)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use redis::commands::Command;
+    use redis::resp::Value;
+    use std::io::{self, Read, Write};
+    use super::{AsyncClient, Client, SyncClient};
+
+    #[test]
+    fn send_writes_the_request_and_decodes_one_reply() {
+        let mut client = Client::new(FakeConn::new(vec![b"+OK\r\n".to_vec()]));
+
+        assert_eq!(
+            Value::SimpleString(b"OK"),
+            client.send(Command::Set { key: b"foo", value: b"bar", options: Default::default() }).unwrap()
+        );
+
+        assert_eq!(
+            b"*3\r\n$3\r\nSET\r\n$3\r\nfoo\r\n$3\r\nbar\r\n".to_vec(),
+            client.stream.output
+        );
+    }
+
+    #[test]
+    fn send_reassembles_a_reply_split_across_reads() {
+        let mut client = Client::new(FakeConn::new(vec![b"$3\r\nfo".to_vec(), b"o\r\n".to_vec()]));
+
+        assert_eq!(
+            Value::BulkString(b"foo"),
+            client.send(Command::Get { key: b"foo" }).unwrap()
+        );
+    }
+
+    #[test]
+    fn drain_collects_pipelined_replies_in_order() {
+        let mut client = Client::new(FakeConn::new(vec![b"+OK\r\n:1\r\n$3\r\nbar\r\n".to_vec()]));
+
+        client.send_nowait(Command::Set { key: b"foo", value: b"bar", options: Default::default() }).unwrap();
+        client.send_nowait(Command::IncrBy { key: b"counter", by: 1 }).unwrap();
+        client.send_nowait(Command::Get { key: b"foo" }).unwrap();
+
+        assert_eq!(
+            vec![
+                Value::SimpleString(b"OK"),
+                Value::Integer(1),
+                Value::BulkString(b"bar"),
+            ],
+            client.drain(3).unwrap()
+        );
+    }
+
+    struct FakeConn {
+        chunks: Vec<Vec<u8>>,
+        output: Vec<u8>,
+    }
+
+    impl FakeConn {
+        fn new(mut chunks: Vec<Vec<u8>>) -> FakeConn {
+            chunks.reverse();
+            FakeConn { chunks: chunks, output: Vec::new() }
+        }
+    }
+
+    impl Read for FakeConn {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            match self.chunks.pop() {
+                Some(chunk) => {
+                    let len = chunk.len();
+                    buf[..len].copy_from_slice(&chunk);
+                    Ok(len)
+                }
+                None => Ok(0),
+            }
+        }
+    }
+
+    impl Write for FakeConn {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.output.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> { Ok(()) }
+    }
+}
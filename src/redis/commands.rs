@@ -7,6 +7,27 @@ pub type Bytes<'a> = &'a [u8];
 pub type IntRange = Range<i64>;
 
 #[derive(PartialEq, Eq, Debug, Clone)]
+pub enum Expiry {
+    Seconds(i64),
+    Millis(i64),
+    UnixSeconds(i64),
+    UnixMillis(i64),
+}
+
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub enum Existence {
+    Nx,
+    Xx,
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Default)]
+pub struct SetOptions {
+    pub expire: Option<Expiry>,
+    pub existence: Option<Existence>,
+    pub keep_ttl: bool,
+}
+
+#[derive(PartialEq, Debug, Clone)]
 pub enum Command<'a> {
     Append { key: Bytes<'a>, value: Bytes<'a> },
     BitCount { key: Bytes<'a>, range: Option<IntRange> },
@@ -17,13 +38,44 @@ pub enum Command<'a> {
     GetRange { key: Bytes<'a>, range: IntRange },
     IncrBy { key: Bytes<'a>, by: i64 },
     LIndex { key: Bytes<'a>, index: i64 },
+    LInsert { key: Bytes<'a>, before: bool, pivot: Bytes<'a>, value: Bytes<'a> },
     LLen { key: Bytes<'a> },
     LPop { key: Bytes<'a> },
     LPush { key: Bytes<'a>, values: &'a [Bytes<'a>] },
+    LRange { key: Bytes<'a>, range: IntRange },
+    LRem { key: Bytes<'a>, count: i64, value: Bytes<'a> },
+    LSet { key: Bytes<'a>, index: i64, value: Bytes<'a> },
+    RPop { key: Bytes<'a> },
+    RPush { key: Bytes<'a>, values: &'a [Bytes<'a>] },
     Rename { key: Bytes<'a>, new_key: Bytes<'a> },
-    Set { key: Bytes<'a>, value: Bytes<'a> },
+    Set { key: Bytes<'a>, value: Bytes<'a>, options: SetOptions },
+    SetRange { key: Bytes<'a>, offset: i64, value: Bytes<'a> },
     Strlen { key: Bytes<'a> },
     Type { key: Bytes<'a> },
+    Multi,
+    Exec,
+    Discard,
+    Watch { keys: &'a [Bytes<'a>] },
+    Dump { key: Bytes<'a> },
+    Restore { key: Bytes<'a>, ttl: i64, serialized: Bytes<'a> },
+    IncrByFloat { key: Bytes<'a>, by: f64 },
+    HDel { key: Bytes<'a>, field: Bytes<'a> },
+    HGet { key: Bytes<'a>, field: Bytes<'a> },
+    HGetAll { key: Bytes<'a> },
+    HLen { key: Bytes<'a> },
+    HSet { key: Bytes<'a>, field: Bytes<'a>, value: Bytes<'a> },
+    Hello { version: Option<i64> },
+    Expire { key: Bytes<'a>, seconds: i64 },
+    Ttl { key: Bytes<'a> },
+    Persist { key: Bytes<'a> },
+    Keys { pattern: Bytes<'a> },
+    // `cursor` is opaque to the client, same as real Redis's SCAN: `b"0"`
+    // starts a fresh scan, and any other value is exactly whatever `SCAN`
+    // last returned — here, the hex-encoded last key it returned, letting
+    // `Database::scan` resume with a `BTreeMap::range` instead of a
+    // position that mid-scan deletes could shift out from under it.
+    Scan { cursor: Bytes<'a>, pattern: Option<Bytes<'a>>, count: Option<usize> },
+    DbSize,
 }
 
 fn slice_to_i64(s: Bytes) -> Option<i64> {
@@ -32,6 +84,97 @@ fn slice_to_i64(s: Bytes) -> Option<i64> {
         .and_then(|s| i64::from_str_radix(s, 10).ok())
 }
 
+fn slice_to_f64(s: Bytes) -> Option<f64> {
+    str::from_utf8(s)
+        .ok()
+        .and_then(|s| s.parse::<f64>().ok())
+        .and_then(|f| if f.is_finite() { Some(f) } else { None })
+}
+
+// Scans the trailing option tokens of a command (currently only `SET`) the
+// way redis-server's own option parsing does: lowercase each token, consume
+// an extra argument for the tokens that take one, and bail on the first
+// unrecognized or conflicting flag. Kept separate from `from_slice` so
+// future variable-arity commands (e.g. `GETEX`) can reuse it.
+pub fn parse_options(cmd: Bytes, tokens: &[Bytes]) -> Result<SetOptions, CommandError> {
+    use redis::database::CommandError::*;
+
+    let mut options = SetOptions::default();
+    let mut i = 0;
+
+    while i < tokens.len() {
+        let token = tokens[i].to_ascii_lowercase();
+
+        match token.as_slice() {
+            b"ex" | b"px" | b"exat" | b"pxat" => {
+                let arg = tokens.get(i + 1).ok_or_else(|| BadCommandSyntax(cmd.to_vec()))?;
+                let n = slice_to_i64(arg).ok_or(NotAnInteger)?;
+
+                options.expire = Some(match token.as_slice() {
+                    b"ex" => Expiry::Seconds(n),
+                    b"px" => Expiry::Millis(n),
+                    b"exat" => Expiry::UnixSeconds(n),
+                    _ => Expiry::UnixMillis(n),
+                });
+
+                i += 2;
+            }
+            b"nx" | b"xx" => {
+                if options.existence.is_some() {
+                    return Err(BadCommandSyntax(cmd.to_vec()));
+                }
+
+                options.existence = Some(if token == b"nx" { Existence::Nx } else { Existence::Xx });
+                i += 1;
+            }
+            b"keepttl" => {
+                options.keep_ttl = true;
+                i += 1;
+            }
+            _ => return Err(BadCommandSyntax(cmd.to_vec())),
+        }
+    }
+
+    Ok(options)
+}
+
+// Scans the trailing option tokens of `SCAN` the same way `parse_options`
+// does for `SET`'s flags: lowercase each token, consume the extra argument
+// `MATCH`/`COUNT` take, and bail on the first unrecognized token.
+pub fn parse_scan_options<'a>(cmd: Bytes, tokens: &[Bytes<'a>]) -> Result<(Option<Bytes<'a>>, Option<usize>), CommandError> {
+    use redis::database::CommandError::*;
+
+    let mut pattern = None;
+    let mut count = None;
+    let mut i = 0;
+
+    while i < tokens.len() {
+        let token = tokens[i].to_ascii_lowercase();
+
+        match token.as_slice() {
+            b"match" => {
+                let arg = tokens.get(i + 1).ok_or_else(|| BadCommandSyntax(cmd.to_vec()))?;
+                pattern = Some(*arg);
+                i += 2;
+            }
+            b"count" => {
+                let arg = tokens.get(i + 1).ok_or_else(|| BadCommandSyntax(cmd.to_vec()))?;
+                let n = slice_to_i64(arg).ok_or(NotAnInteger)?;
+
+                if n <= 0 {
+                    return Err(BadCommandSyntax(cmd.to_vec()));
+                }
+
+                count = Some(n as usize);
+                i += 2;
+            }
+            _ => return Err(BadCommandSyntax(cmd.to_vec())),
+        }
+    }
+
+    Ok((pattern, count))
+}
+
 macro_rules! key_value {
     ( $cmd:ident, $slice:ident, $f:expr ) => {
         match &$slice[1..] {
@@ -54,6 +197,19 @@ macro_rules! key_int {
     };
 }
 
+macro_rules! key_float {
+    ( $cmd:ident, $slice:ident, $f:expr ) => {
+        match &$slice[1..] {
+            &[key, value] =>
+                slice_to_f64(value)
+                    .ok_or(NotAFloat)
+                    .map(|f| $f(key, f)),
+            _ =>
+                Err(BadCommandAryth($cmd)),
+        }
+    };
+}
+
 macro_rules! string {
     ( $cmd:ident, $slice:ident, $f:expr ) => {
         match &$slice[1..] {
@@ -117,7 +273,179 @@ macro_rules! key_values {
     };
 }
 
+fn encode_args(args: &[Vec<u8>]) -> Vec<u8> {
+    let mut out = Vec::new();
+
+    out.extend_from_slice(format!("*{}\r\n", args.len()).as_bytes());
+
+    for arg in args {
+        out.extend_from_slice(format!("${}\r\n", arg.len()).as_bytes());
+        out.extend_from_slice(arg);
+        out.extend_from_slice(b"\r\n");
+    }
+
+    out
+}
+
+fn encode_set_options(options: &SetOptions, args: &mut Vec<Vec<u8>>) {
+    match options.expire {
+        Some(Expiry::Seconds(n)) => args.extend(vec![b"EX".to_vec(), format!("{}", n).into_bytes()]),
+        Some(Expiry::Millis(n)) => args.extend(vec![b"PX".to_vec(), format!("{}", n).into_bytes()]),
+        Some(Expiry::UnixSeconds(n)) => args.extend(vec![b"EXAT".to_vec(), format!("{}", n).into_bytes()]),
+        Some(Expiry::UnixMillis(n)) => args.extend(vec![b"PXAT".to_vec(), format!("{}", n).into_bytes()]),
+        None => {}
+    }
+
+    match options.existence {
+        Some(Existence::Nx) => args.push(b"NX".to_vec()),
+        Some(Existence::Xx) => args.push(b"XX".to_vec()),
+        None => {}
+    }
+
+    if options.keep_ttl {
+        args.push(b"KEEPTTL".to_vec());
+    }
+}
+
 impl<'a> Command<'a> {
+    // Serializes the command back into a RESP array-of-bulk-strings, the
+    // same wire shape a client would have sent. Kept as the mirror image of
+    // `from_slice` so the two can be checked against each other with a
+    // round-trip property instead of drifting apart silently.
+    pub fn to_resp(&self) -> Vec<u8> {
+        use self::Command::*;
+
+        let args: Vec<Vec<u8>> = match self.clone() {
+            Append { key, value } =>
+                vec![b"APPEND".to_vec(), key.to_vec(), value.to_vec()],
+            BitCount { key, range: None } =>
+                vec![b"BITCOUNT".to_vec(), key.to_vec()],
+            BitCount { key, range: Some(range) } =>
+                vec![b"BITCOUNT".to_vec(), key.to_vec(), format!("{}", range.start).into_bytes(), format!("{}", range.end).into_bytes()],
+            DecrBy { key, by } =>
+                vec![b"DECRBY".to_vec(), key.to_vec(), format!("{}", by).into_bytes()],
+            Del { keys } => {
+                let mut args = vec![b"DEL".to_vec()];
+                args.extend(keys.iter().map(|k| k.to_vec()));
+                args
+            }
+            Exists { keys } => {
+                let mut args = vec![b"EXISTS".to_vec()];
+                args.extend(keys.iter().map(|k| k.to_vec()));
+                args
+            }
+            Get { key } =>
+                vec![b"GET".to_vec(), key.to_vec()],
+            GetRange { key, range } =>
+                vec![b"GETRANGE".to_vec(), key.to_vec(), format!("{}", range.start).into_bytes(), format!("{}", range.end).into_bytes()],
+            IncrBy { key, by } =>
+                vec![b"INCRBY".to_vec(), key.to_vec(), format!("{}", by).into_bytes()],
+            LIndex { key, index } =>
+                vec![b"LINDEX".to_vec(), key.to_vec(), format!("{}", index).into_bytes()],
+            LInsert { key, before, pivot, value } =>
+                vec![
+                    b"LINSERT".to_vec(),
+                    key.to_vec(),
+                    if before { b"BEFORE".to_vec() } else { b"AFTER".to_vec() },
+                    pivot.to_vec(),
+                    value.to_vec(),
+                ],
+            LLen { key } =>
+                vec![b"LLEN".to_vec(), key.to_vec()],
+            LPop { key } =>
+                vec![b"LPOP".to_vec(), key.to_vec()],
+            LPush { key, values } => {
+                let mut args = vec![b"LPUSH".to_vec(), key.to_vec()];
+                args.extend(values.iter().map(|v| v.to_vec()));
+                args
+            }
+            LRange { key, range } =>
+                vec![b"LRANGE".to_vec(), key.to_vec(), format!("{}", range.start).into_bytes(), format!("{}", range.end).into_bytes()],
+            LRem { key, count, value } =>
+                vec![b"LREM".to_vec(), key.to_vec(), format!("{}", count).into_bytes(), value.to_vec()],
+            LSet { key, index, value } =>
+                vec![b"LSET".to_vec(), key.to_vec(), format!("{}", index).into_bytes(), value.to_vec()],
+            RPop { key } =>
+                vec![b"RPOP".to_vec(), key.to_vec()],
+            RPush { key, values } => {
+                let mut args = vec![b"RPUSH".to_vec(), key.to_vec()];
+                args.extend(values.iter().map(|v| v.to_vec()));
+                args
+            }
+            Rename { key, new_key } =>
+                vec![b"RENAME".to_vec(), key.to_vec(), new_key.to_vec()],
+            Set { key, value, options } => {
+                let mut args = vec![b"SET".to_vec(), key.to_vec(), value.to_vec()];
+                encode_set_options(&options, &mut args);
+                args
+            }
+            SetRange { key, offset, value } =>
+                vec![b"SETRANGE".to_vec(), key.to_vec(), format!("{}", offset).into_bytes(), value.to_vec()],
+            Strlen { key } =>
+                vec![b"STRLEN".to_vec(), key.to_vec()],
+            Type { key } =>
+                vec![b"TYPE".to_vec(), key.to_vec()],
+            Multi =>
+                vec![b"MULTI".to_vec()],
+            Exec =>
+                vec![b"EXEC".to_vec()],
+            Discard =>
+                vec![b"DISCARD".to_vec()],
+            Watch { keys } => {
+                let mut args = vec![b"WATCH".to_vec()];
+                args.extend(keys.iter().map(|k| k.to_vec()));
+                args
+            }
+            Dump { key } =>
+                vec![b"DUMP".to_vec(), key.to_vec()],
+            Restore { key, ttl, serialized } =>
+                vec![b"RESTORE".to_vec(), key.to_vec(), format!("{}", ttl).into_bytes(), serialized.to_vec()],
+            IncrByFloat { key, by } =>
+                vec![b"INCRBYFLOAT".to_vec(), key.to_vec(), format!("{}", by).into_bytes()],
+            HDel { key, field } =>
+                vec![b"HDEL".to_vec(), key.to_vec(), field.to_vec()],
+            HGet { key, field } =>
+                vec![b"HGET".to_vec(), key.to_vec(), field.to_vec()],
+            HGetAll { key } =>
+                vec![b"HGETALL".to_vec(), key.to_vec()],
+            HLen { key } =>
+                vec![b"HLEN".to_vec(), key.to_vec()],
+            HSet { key, field, value } =>
+                vec![b"HSET".to_vec(), key.to_vec(), field.to_vec(), value.to_vec()],
+            Hello { version: None } =>
+                vec![b"HELLO".to_vec()],
+            Hello { version: Some(version) } =>
+                vec![b"HELLO".to_vec(), format!("{}", version).into_bytes()],
+            Expire { key, seconds } =>
+                vec![b"EXPIRE".to_vec(), key.to_vec(), format!("{}", seconds).into_bytes()],
+            Ttl { key } =>
+                vec![b"TTL".to_vec(), key.to_vec()],
+            Persist { key } =>
+                vec![b"PERSIST".to_vec(), key.to_vec()],
+            Keys { pattern } =>
+                vec![b"KEYS".to_vec(), pattern.to_vec()],
+            Scan { cursor, pattern, count } => {
+                let mut args = vec![b"SCAN".to_vec(), cursor.to_vec()];
+
+                if let Some(pattern) = pattern {
+                    args.push(b"MATCH".to_vec());
+                    args.push(pattern.to_vec());
+                }
+
+                if let Some(count) = count {
+                    args.push(b"COUNT".to_vec());
+                    args.push(format!("{}", count).into_bytes());
+                }
+
+                args
+            }
+            DbSize =>
+                vec![b"DBSIZE".to_vec()],
+        };
+
+        encode_args(&args)
+    }
+
     pub fn from_slice(s: &'a [Bytes<'a>]) -> Result<Command<'a>, CommandError> {
         use redis::database::CommandError::*;
         use self::Command::*;
@@ -131,7 +459,12 @@ impl<'a> Command<'a> {
         match cmd.as_slice() {
             b"append"   => key_value!(cmd, s, |k, v| Append { key: k, value: v }),
             b"rename"   => key_value!(cmd, s, |k, nk| Rename { key: k, new_key: nk }),
-            b"set"      => key_value!(cmd, s, |k, v| Set { key: k, value: v }),
+            b"set"      => match &s[1..] {
+                &[key, value, ref rest..] =>
+                    parse_options(&cmd, rest).map(|options| Set { key: key, value: value, options: options }),
+                _ =>
+                    Err(BadCommandAryth(cmd)),
+            },
             b"decr"     => string!(cmd, s, |k| DecrBy { key: k, by: 1 }),
             b"get"      => string!(cmd, s, |k| Get { key: k }),
             b"incr"     => string!(cmd, s, |k| IncrBy { key: k, by: 1 }),
@@ -144,9 +477,89 @@ impl<'a> Command<'a> {
             b"decrby"   => key_int!(cmd, s, |k, i| DecrBy { key: k, by: i }),
             b"incrby"   => key_int!(cmd, s, |k, i| IncrBy { key: k, by: i }),
             b"lindex"   => key_int!(cmd, s, |k, i| LIndex { key: k, index: i }),
+            b"lset"     => match &s[1..] {
+                &[key, index, value] =>
+                    slice_to_i64(index)
+                        .ok_or(NotAnInteger)
+                        .map(|index| LSet { key: key, index: index, value: value }),
+                _ =>
+                    Err(BadCommandAryth(cmd)),
+            },
+            b"lrem"     => match &s[1..] {
+                &[key, count, value] =>
+                    slice_to_i64(count)
+                        .ok_or(NotAnInteger)
+                        .map(|count| LRem { key: key, count: count, value: value }),
+                _ =>
+                    Err(BadCommandAryth(cmd)),
+            },
+            b"linsert"  => match &s[1..] {
+                &[key, where_, pivot, value] => {
+                    match where_.to_ascii_lowercase().as_slice() {
+                        b"before" => Ok(LInsert { key: key, before: true, pivot: pivot, value: value }),
+                        b"after"  => Ok(LInsert { key: key, before: false, pivot: pivot, value: value }),
+                        _         => Err(BadCommandSyntax(cmd)),
+                    }
+                }
+                _ =>
+                    Err(BadCommandAryth(cmd)),
+            },
             b"bitcount" => key_range_opt!(cmd, s, |k, r| BitCount { key: k, range: r }),
             b"getrange" => key_range!(cmd, s, |k, r| GetRange { key: k, range: r }),
             b"lpush"    => key_values!(cmd, s, |k, vs| LPush { key: k, values: vs }),
+            b"rpush"    => key_values!(cmd, s, |k, vs| RPush { key: k, values: vs }),
+            b"rpop"     => string!(cmd, s, |k| RPop { key: k }),
+            b"lrange"   => key_range!(cmd, s, |k, r| LRange { key: k, range: r }),
+            b"hget"     => key_value!(cmd, s, |k, f| HGet { key: k, field: f }),
+            b"hdel"     => key_value!(cmd, s, |k, f| HDel { key: k, field: f }),
+            b"hlen"     => string!(cmd, s, |k| HLen { key: k }),
+            b"hgetall"  => string!(cmd, s, |k| HGetAll { key: k }),
+            b"hset"     => match &s[1..] {
+                &[key, field, value] => Ok(HSet { key: key, field: field, value: value }),
+                _                    => Err(BadCommandAryth(cmd)),
+            },
+            b"multi"    => if s.len() == 1 { Ok(Multi) } else { Err(BadCommandAryth(cmd)) },
+            b"exec"     => if s.len() == 1 { Ok(Exec) } else { Err(BadCommandAryth(cmd)) },
+            b"discard"  => if s.len() == 1 { Ok(Discard) } else { Err(BadCommandAryth(cmd)) },
+            b"watch"    => keys!(cmd, s, |ks| Watch { keys: ks }),
+            b"dump"     => string!(cmd, s, |k| Dump { key: k }),
+            b"restore"  => match &s[1..] {
+                &[key, ttl, serialized] =>
+                    slice_to_i64(ttl)
+                        .ok_or(NotAnInteger)
+                        .map(|ttl| Restore { key: key, ttl: ttl, serialized: serialized }),
+                _ =>
+                    Err(BadCommandAryth(cmd)),
+            },
+            b"incrbyfloat" => key_float!(cmd, s, |k, f| IncrByFloat { key: k, by: f }),
+            b"setrange" => match &s[1..] {
+                &[key, offset, value] =>
+                    slice_to_i64(offset)
+                        .ok_or(NotAnInteger)
+                        .map(|offset| SetRange { key: key, offset: offset, value: value }),
+                _ =>
+                    Err(BadCommandAryth(cmd)),
+            },
+            b"hello"    => match &s[1..] {
+                &[] => Ok(Hello { version: None }),
+                &[version] =>
+                    slice_to_i64(version)
+                        .ok_or(NotAnInteger)
+                        .map(|v| Hello { version: Some(v) }),
+                _ => Err(BadCommandAryth(cmd)),
+            },
+            b"expire"   => key_int!(cmd, s, |k, secs| Expire { key: k, seconds: secs }),
+            b"ttl"      => string!(cmd, s, |k| Ttl { key: k }),
+            b"persist"  => string!(cmd, s, |k| Persist { key: k }),
+            b"keys"     => string!(cmd, s, |k| Keys { pattern: k }),
+            b"scan"     => match &s[1..] {
+                &[cursor, ref rest..] =>
+                    parse_scan_options(&cmd, rest)
+                        .map(|(pattern, count)| Scan { cursor: cursor, pattern: pattern, count: count }),
+                _ =>
+                    Err(BadCommandAryth(cmd)),
+            },
+            b"dbsize"   => if s.len() == 1 { Ok(DbSize) } else { Err(BadCommandAryth(cmd)) },
             _           => Err(UnknownCommand(s[0].to_vec())),
         }
     }
@@ -154,9 +567,138 @@ impl<'a> Command<'a> {
 
 #[cfg(test)]
 mod test {
+    use nom::IResult;
     use redis::database::CommandError::*;
+    use redis::resp::{decode, Value};
     use super::Command::*;
-    use super::Command;
+    use super::{Command, Existence, Expiry, SetOptions};
+
+    fn round_trips(cmd: &Command) -> bool {
+        let encoded = cmd.to_resp();
+
+        let items = match decode(&encoded) {
+            IResult::Done(rest, Value::Array(items)) => {
+                assert!(rest.is_empty());
+                items
+            }
+            other => panic!("expected a RESP array, got {:?}", other),
+        };
+
+        let tokens: Vec<&[u8]> = items.iter().map(|v| match *v {
+            Value::BulkString(b) => b,
+            ref other => panic!("expected a bulk string, got {:?}", other),
+        }).collect();
+
+        Command::from_slice(&tokens) == Ok(cmd.clone())
+    }
+
+    #[test]
+    fn to_resp_round_trips_every_variant() {
+        assert!(round_trips(&Append { key: b"foo", value: b"bar" }));
+        assert!(round_trips(&BitCount { key: b"foo", range: None }));
+        assert!(round_trips(&BitCount { key: b"foo", range: Some(-1..25) }));
+        assert!(round_trips(&DecrBy { key: b"foo", by: -4 }));
+        assert!(round_trips(&Del { keys: &[b"foo", b"bar"] }));
+        assert!(round_trips(&Exists { keys: &[b"foo", b"bar"] }));
+        assert!(round_trips(&Get { key: b"foo" }));
+        assert!(round_trips(&GetRange { key: b"foo", range: -1..25 }));
+        assert!(round_trips(&IncrBy { key: b"foo", by: 42 }));
+        assert!(round_trips(&LIndex { key: b"foo", index: -2 }));
+        assert!(round_trips(&LInsert { key: b"foo", before: true, pivot: b"a", value: b"b" }));
+        assert!(round_trips(&LInsert { key: b"foo", before: false, pivot: b"a", value: b"b" }));
+        assert!(round_trips(&LLen { key: b"foo" }));
+        assert!(round_trips(&LPop { key: b"foo" }));
+        assert!(round_trips(&LPush { key: b"foo", values: &[b"a", b"b", b"c"] }));
+        assert!(round_trips(&LRange { key: b"foo", range: -1..25 }));
+        assert!(round_trips(&LRem { key: b"foo", count: -2, value: b"a" }));
+        assert!(round_trips(&LSet { key: b"foo", index: -1, value: b"a" }));
+        assert!(round_trips(&RPop { key: b"foo" }));
+        assert!(round_trips(&RPush { key: b"foo", values: &[b"a", b"b", b"c"] }));
+        assert!(round_trips(&Rename { key: b"foo", new_key: b"bar" }));
+        assert!(round_trips(&Set { key: b"foo", value: b"bar", options: SetOptions::default() }));
+        assert!(round_trips(&Set {
+            key: b"foo",
+            value: b"bar",
+            options: SetOptions {
+                expire: Some(Expiry::Seconds(10)),
+                existence: Some(Existence::Nx),
+                keep_ttl: false,
+            },
+        }));
+        assert!(round_trips(&Set {
+            key: b"foo",
+            value: b"bar",
+            options: SetOptions { keep_ttl: true, ..SetOptions::default() },
+        }));
+        assert!(round_trips(&SetRange { key: b"foo", offset: 5, value: b"bar" }));
+        assert!(round_trips(&Strlen { key: b"foo" }));
+        assert!(round_trips(&Type { key: b"foo" }));
+        assert!(round_trips(&Multi));
+        assert!(round_trips(&Exec));
+        assert!(round_trips(&Discard));
+        assert!(round_trips(&Watch { keys: &[b"foo", b"bar"] }));
+        assert!(round_trips(&Dump { key: b"foo" }));
+        assert!(round_trips(&Restore { key: b"foo", ttl: 0, serialized: b"deadbeef" }));
+        assert!(round_trips(&IncrByFloat { key: b"foo", by: 3.25 }));
+        assert!(round_trips(&HDel { key: b"foo", field: b"a" }));
+        assert!(round_trips(&HGet { key: b"foo", field: b"a" }));
+        assert!(round_trips(&HGetAll { key: b"foo" }));
+        assert!(round_trips(&HLen { key: b"foo" }));
+        assert!(round_trips(&HSet { key: b"foo", field: b"a", value: b"1" }));
+        assert!(round_trips(&Hello { version: None }));
+        assert!(round_trips(&Hello { version: Some(3) }));
+        assert!(round_trips(&Expire { key: b"foo", seconds: 10 }));
+        assert!(round_trips(&Ttl { key: b"foo" }));
+        assert!(round_trips(&Persist { key: b"foo" }));
+        assert!(round_trips(&Keys { pattern: b"foo*" }));
+        assert!(round_trips(&Scan { cursor: b"0", pattern: None, count: None }));
+        assert!(round_trips(&Scan { cursor: b"666f6f", pattern: Some(b"foo*"), count: Some(10) }));
+        assert!(round_trips(&DbSize));
+    }
+
+    #[quickcheck]
+    fn to_resp_round_trips_incr_by(by: i64) -> bool {
+        round_trips(&IncrBy { key: b"foo", by: by })
+    }
+
+    #[quickcheck]
+    fn to_resp_round_trips_decr_by(by: i64) -> bool {
+        round_trips(&DecrBy { key: b"foo", by: by })
+    }
+
+    #[quickcheck]
+    fn to_resp_round_trips_lindex(index: i64) -> bool {
+        round_trips(&LIndex { key: b"foo", index: index })
+    }
+
+    #[quickcheck]
+    fn to_resp_round_trips_lset(index: i64) -> bool {
+        round_trips(&LSet { key: b"foo", index: index, value: b"bar" })
+    }
+
+    #[quickcheck]
+    fn to_resp_round_trips_lrem(count: i64) -> bool {
+        round_trips(&LRem { key: b"foo", count: count, value: b"bar" })
+    }
+
+    #[quickcheck]
+    fn to_resp_round_trips_get_range(start: i64, end: i64) -> bool {
+        round_trips(&GetRange { key: b"foo", range: start..end })
+    }
+
+    #[quickcheck]
+    fn to_resp_round_trips_set_range(offset: i64) -> bool {
+        round_trips(&SetRange { key: b"foo", offset: offset, value: b"bar" })
+    }
+
+    #[quickcheck]
+    fn to_resp_round_trips_incr_by_float(by: f64) -> bool {
+        if !by.is_finite() {
+            return true;
+        }
+
+        round_trips(&IncrByFloat { key: b"foo", by: by })
+    }
 
     #[test]
     fn append() {
@@ -169,11 +711,54 @@ mod test {
     #[test]
     fn set() {
         assert_eq!(
-            Ok(Set { key: b"foo", value: b"bar" }),
+            Ok(Set { key: b"foo", value: b"bar", options: SetOptions::default() }),
             Command::from_slice(&[b"set", b"foo", b"bar"])
         );
     }
 
+    #[test]
+    fn set_with_options() {
+        assert_eq!(
+            Ok(Set {
+                key: b"foo",
+                value: b"bar",
+                options: SetOptions {
+                    expire: Some(Expiry::Seconds(10)),
+                    existence: Some(Existence::Nx),
+                    keep_ttl: false,
+                },
+            }),
+            Command::from_slice(&[b"set", b"foo", b"bar", b"EX", b"10", b"NX"])
+        );
+
+        assert_eq!(
+            Ok(Set {
+                key: b"foo",
+                value: b"bar",
+                options: SetOptions { keep_ttl: true, ..SetOptions::default() },
+            }),
+            Command::from_slice(&[b"set", b"foo", b"bar", b"KEEPTTL"])
+        );
+    }
+
+    #[test]
+    fn set_with_bad_options() {
+        assert_eq!(
+            Err(BadCommandSyntax(b"set".to_vec())),
+            Command::from_slice(&[b"set", b"foo", b"bar", b"NX", b"XX"])
+        );
+
+        assert_eq!(
+            Err(NotAnInteger),
+            Command::from_slice(&[b"set", b"foo", b"bar", b"EX", b"nope"])
+        );
+
+        assert_eq!(
+            Err(BadCommandSyntax(b"set".to_vec())),
+            Command::from_slice(&[b"set", b"foo", b"bar", b"NOPE"])
+        );
+    }
+
     #[test]
     fn get() {
         assert_eq!(
@@ -328,6 +913,22 @@ mod test {
         );
     }
 
+    #[test]
+    fn set_range() {
+        assert_eq!(
+            Ok(SetRange { key: b"foo", offset: 5, value: b"bar" }),
+            Command::from_slice(&[b"setrange", b"foo", b"5", b"bar"])
+        );
+    }
+
+    #[test]
+    fn set_range_not_an_integer() {
+        assert_eq!(
+            Err(NotAnInteger),
+            Command::from_slice(&[b"setrange", b"foo", b"bar", b"baz"])
+        );
+    }
+
     #[test]
     fn lpush() {
         assert_eq!(
@@ -335,4 +936,193 @@ mod test {
             Command::from_slice(&[b"lpush", b"foo", b"a", b"b", b"c"])
         );
     }
+
+    #[test]
+    fn lset() {
+        assert_eq!(
+            Ok(LSet { key: b"foo", index: -1, value: b"bar" }),
+            Command::from_slice(&[b"lset", b"foo", b"-1", b"bar"])
+        );
+    }
+
+    #[test]
+    fn lset_not_an_integer() {
+        assert_eq!(
+            Err(NotAnInteger),
+            Command::from_slice(&[b"lset", b"foo", b"bar", b"baz"])
+        );
+    }
+
+    #[test]
+    fn lrem() {
+        assert_eq!(
+            Ok(LRem { key: b"foo", count: -2, value: b"bar" }),
+            Command::from_slice(&[b"lrem", b"foo", b"-2", b"bar"])
+        );
+    }
+
+    #[test]
+    fn lrem_not_an_integer() {
+        assert_eq!(
+            Err(NotAnInteger),
+            Command::from_slice(&[b"lrem", b"foo", b"bar", b"baz"])
+        );
+    }
+
+    #[test]
+    fn linsert() {
+        assert_eq!(
+            Ok(LInsert { key: b"foo", before: true, pivot: b"a", value: b"b" }),
+            Command::from_slice(&[b"linsert", b"foo", b"before", b"a", b"b"])
+        );
+
+        assert_eq!(
+            Ok(LInsert { key: b"foo", before: false, pivot: b"a", value: b"b" }),
+            Command::from_slice(&[b"linsert", b"foo", b"AFTER", b"a", b"b"])
+        );
+    }
+
+    #[test]
+    fn linsert_bad_where() {
+        assert_eq!(
+            Err(BadCommandSyntax(b"linsert".to_vec())),
+            Command::from_slice(&[b"linsert", b"foo", b"nope", b"a", b"b"])
+        );
+    }
+
+    #[test]
+    fn multi() {
+        assert_eq!(Ok(Multi), Command::from_slice(&[b"multi"]));
+    }
+
+    #[test]
+    fn exec() {
+        assert_eq!(Ok(Exec), Command::from_slice(&[b"exec"]));
+    }
+
+    #[test]
+    fn discard() {
+        assert_eq!(Ok(Discard), Command::from_slice(&[b"discard"]));
+    }
+
+    #[test]
+    fn watch() {
+        assert_eq!(
+            Ok(Watch { keys: &[b"foo", b"bar"] }),
+            Command::from_slice(&[b"watch", b"foo", b"bar"])
+        );
+    }
+
+    #[test]
+    fn dump() {
+        assert_eq!(
+            Ok(Dump { key: b"foo" }),
+            Command::from_slice(&[b"dump", b"foo"])
+        );
+    }
+
+    #[test]
+    fn restore() {
+        assert_eq!(
+            Ok(Restore { key: b"foo", ttl: 0, serialized: b"deadbeef" }),
+            Command::from_slice(&[b"restore", b"foo", b"0", b"deadbeef"])
+        );
+    }
+
+    #[test]
+    fn expire() {
+        assert_eq!(
+            Ok(Expire { key: b"foo", seconds: 10 }),
+            Command::from_slice(&[b"expire", b"foo", b"10"])
+        );
+    }
+
+    #[test]
+    fn ttl() {
+        assert_eq!(
+            Ok(Ttl { key: b"foo" }),
+            Command::from_slice(&[b"ttl", b"foo"])
+        );
+    }
+
+    #[test]
+    fn persist() {
+        assert_eq!(
+            Ok(Persist { key: b"foo" }),
+            Command::from_slice(&[b"persist", b"foo"])
+        );
+    }
+
+    #[test]
+    fn keys() {
+        assert_eq!(
+            Ok(Keys { pattern: b"foo*" }),
+            Command::from_slice(&[b"keys", b"foo*"])
+        );
+    }
+
+    #[test]
+    fn scan() {
+        assert_eq!(
+            Ok(Scan { cursor: b"0", pattern: None, count: None }),
+            Command::from_slice(&[b"scan", b"0"])
+        );
+        assert_eq!(
+            Ok(Scan { cursor: b"666f6f", pattern: Some(b"foo*"), count: Some(10) }),
+            Command::from_slice(&[b"scan", b"666f6f", b"match", b"foo*", b"count", b"10"])
+        );
+        assert!(Command::from_slice(&[b"scan", b"0", b"bogus"]).is_err());
+    }
+
+    #[test]
+    fn dbsize() {
+        assert_eq!(Ok(DbSize), Command::from_slice(&[b"dbsize"]));
+        assert!(Command::from_slice(&[b"dbsize", b"foo"]).is_err());
+    }
+
+    #[quickcheck]
+    fn incr_by_float(by: f64) -> bool {
+        if !by.is_finite() {
+            return true;
+        }
+
+        let as_str = format!("{}", by);
+
+        match Command::from_slice(&[b"incrbyfloat", b"foo", as_str.as_bytes()]) {
+            Ok(IncrByFloat { key: b"foo", by: parsed }) => parsed == by,
+            _ => false,
+        }
+    }
+
+    #[test]
+    fn incr_by_float_rejects_non_numeric() {
+        assert_eq!(
+            Err(NotAFloat),
+            Command::from_slice(&[b"incrbyfloat", b"foo", b"nope"])
+        );
+    }
+
+    #[test]
+    fn incr_by_float_rejects_nan_and_inf() {
+        assert_eq!(
+            Err(NotAFloat),
+            Command::from_slice(&[b"incrbyfloat", b"foo", b"nan"])
+        );
+
+        assert_eq!(
+            Err(NotAFloat),
+            Command::from_slice(&[b"incrbyfloat", b"foo", b"inf"])
+        );
+    }
+
+    #[test]
+    fn hello() {
+        assert_eq!(Ok(Hello { version: None }), Command::from_slice(&[b"hello"]));
+        assert_eq!(Ok(Hello { version: Some(3) }), Command::from_slice(&[b"hello", b"3"]));
+    }
+
+    #[test]
+    fn hello_rejects_non_numeric_version() {
+        assert_eq!(Err(NotAnInteger), Command::from_slice(&[b"hello", b"nope"]));
+    }
 }
@@ -1,3 +1,13 @@
+// This file is never `mod`-included from `main.rs` (there's no `redis::lib`
+// anywhere in the crate), so nothing here ships. chunk1-1's RESP3/HELLO
+// negotiation landed here instead of in the live command/protocol path;
+// that request is superseded by chunk2-1, which added `HELLO` support to
+// `commands.rs`/`database.rs` directly. Likewise chunk1-2's binary-safe
+// multibulk request parsing is superseded by chunk5-1's fix wiring
+// `line::tokenize_request` into `tcp.rs`'s actual dispatch, and chunk1-5's
+// glob key matching for `KEYS`/`SCAN` is superseded by the real
+// `glob_match`/`scan` in `database.rs` (the latter reworked by chunk3-3
+// to cursor correctly and match without recursive backtracking).
 #[macro_use]
 extern crate nom;
 
@@ -1,4 +1,5 @@
-use nom::{multispace, crlf};
+use nom::{multispace, crlf, digit, Err, ErrorKind, IResult, Needed};
+use std::str::{self, FromStr};
 
 fn not_multispace(c: u8) -> bool {
     match c {
@@ -7,14 +8,136 @@ fn not_multispace(c: u8) -> bool {
     }
 }
 
-named!(string,
-   alt!(
-       delimited!(char!('"'), take_until!("\""), char!('"'))
-     | take_while!(not_multispace)
-   )
-);
+fn hex_val(b: u8) -> Option<u8> {
+    match b {
+        b'0'...b'9' => Some(b - b'0'),
+        b'a'...b'f' => Some(b - b'a' + 10),
+        b'A'...b'F' => Some(b - b'A' + 10),
+        _ => None,
+    }
+}
+
+// `quoted`'s two hand-checked failure modes are tagged with a `Custom`
+// `ErrorKind` so `tokenize_request` can tell them apart later; nom's own
+// macros (`tag!`, `digit`, ...) use their own built-in `ErrorKind`s for
+// everything else, which `tokenize_request` reports as a generic
+// `Reason::Malformed`.
+const BAD_HEX_ESCAPE: u32 = 1;
+const TRAILING_GARBAGE_AFTER_QUOTE: u32 = 2;
+const MULTIBULK_LEN_TOO_LARGE: u32 = 3;
+
+// No real client ever sends anywhere near this many arguments in one
+// request; the bound exists purely to keep a declared `*<argc>` from
+// reaching `count!` below, whose `Vec::with_capacity(argc)` panics
+// outright once `argc * size_of::<T>()` overflows `isize::MAX` — `size`
+// only rejects what doesn't fit in a `usize`, so a ~20-byte request like
+// `*600000000000000000\r\n` would otherwise crash the whole process.
+const MAX_MULTIBULK_LEN: usize = 1024 * 1024;
+
+fn bounded_argc(input: &[u8]) -> IResult<&[u8], usize> {
+    match size(input) {
+        IResult::Done(rest, argc) => {
+            if argc > MAX_MULTIBULK_LEN {
+                IResult::Error(Err::Position(ErrorKind::Custom(MULTIBULK_LEN_TOO_LARGE), input))
+            } else {
+                IResult::Done(rest, argc)
+            }
+        }
+        other => other,
+    }
+}
 
-named!(pub tokenize<Vec<&[u8]> >,
+/// A token is a double-quoted string, a single-quoted string, or a bare
+/// run of non-whitespace bytes — the same three shapes `redis-server`'s
+/// `sdssplitargs` recognizes in an inline command. Quoted tokens may
+/// decode to bytes that don't exist contiguously in `input` (escapes,
+/// embedded whitespace), so unlike a plain slice-and-split this has to
+/// hand back an owned `Vec<u8>` per token.
+fn string(input: &[u8]) -> IResult<&[u8], Vec<u8>> {
+    match input.first() {
+        Some(&b'"') => quoted(input, b'"', true),
+        Some(&b'\'') => quoted(input, b'\'', false),
+        Some(_) => {
+            let end = input.iter().position(|&b| !not_multispace(b)).unwrap_or(input.len());
+            IResult::Done(&input[end..], input[..end].to_vec())
+        }
+        None => IResult::Incomplete(Needed::Size(1)),
+    }
+}
+
+/// Scans the body of a `"..."` or `'...'` token, starting at its opening
+/// `quote`. `decode_escapes` picks double-quote semantics (the full
+/// `sdssplitargs` escape table below) versus single-quote semantics
+/// (only `\'` is special, everything else is literal). A run short of
+/// its closing quote is `Incomplete` rather than an error, same as a
+/// `bulk_string` short of its declared length — the rest may still be on
+/// its way in over the wire. Once the closing quote is found, it must be
+/// immediately followed by whitespace or end-of-input; `sdssplitargs`
+/// rejects a quote glued to trailing garbage, and so do we.
+fn quoted(input: &[u8], quote: u8, decode_escapes: bool) -> IResult<&[u8], Vec<u8>> {
+    let mut out = Vec::new();
+    let mut i = 1;
+
+    loop {
+        match input.get(i) {
+            None => return IResult::Incomplete(Needed::Size(1)),
+            Some(&b) if b == quote => {
+                i += 1;
+                let closed_cleanly = match input.get(i) {
+                    None => true,
+                    Some(&next) => !not_multispace(next),
+                };
+
+                return if closed_cleanly {
+                    IResult::Done(&input[i..], out)
+                } else {
+                    IResult::Error(Err::Position(
+                        ErrorKind::Custom(TRAILING_GARBAGE_AFTER_QUOTE),
+                        &input[i..],
+                    ))
+                };
+            }
+            Some(&b'\\') if decode_escapes => {
+                match input.get(i + 1) {
+                    None => return IResult::Incomplete(Needed::Size(1)),
+                    Some(&b'x') => {
+                        match (input.get(i + 2), input.get(i + 3)) {
+                            (Some(&hi), Some(&lo)) => {
+                                match (hex_val(hi), hex_val(lo)) {
+                                    (Some(hi), Some(lo)) => {
+                                        out.push((hi << 4) | lo);
+                                        i += 4;
+                                    }
+                                    _ => return IResult::Error(Err::Position(
+                                        ErrorKind::Custom(BAD_HEX_ESCAPE),
+                                        &input[i..],
+                                    )),
+                                }
+                            }
+                            _ => return IResult::Incomplete(Needed::Size(1)),
+                        }
+                    }
+                    Some(&b'n') => { out.push(b'\n'); i += 2; }
+                    Some(&b'r') => { out.push(b'\r'); i += 2; }
+                    Some(&b't') => { out.push(b'\t'); i += 2; }
+                    Some(&b'b') => { out.push(0x08); i += 2; }
+                    Some(&b'a') => { out.push(0x07); i += 2; }
+                    Some(&escaped) => { out.push(escaped); i += 2; }
+                }
+            }
+            Some(&b'\\') if !decode_escapes => {
+                match input.get(i + 1) {
+                    None => return IResult::Incomplete(Needed::Size(1)),
+                    Some(&b'\'') => { out.push(b'\''); i += 2; }
+                    Some(_) => { out.push(b'\\'); i += 1; }
+                }
+            }
+            Some(&b) => { out.push(b); i += 1; }
+        }
+    }
+}
+
+named!(pub tokenize<Vec<Vec<u8>> >,
     chain!(
         l: separated_list!(multispace, string) ~
         crlf,
@@ -22,10 +145,133 @@ named!(pub tokenize<Vec<&[u8]> >,
     )
 );
 
+// `digit` only matches `[0-9]`, so a leading `-` simply fails to parse
+// instead of producing a negative length; `FromStr::from_str` on `usize`
+// rejects anything too large to fit. Either way `bulk_string` never sees
+// a length it has to second-guess.
+named!(size<usize>,
+    map_res!(
+        map_res!(digit, str::from_utf8),
+        FromStr::from_str
+    )
+);
+
+named!(bulk_string,
+    chain!(
+        size: size ~
+        crlf ~
+        bulk: take!(size) ~
+        crlf,
+        || bulk
+    )
+);
+
+/// Parses a RESP multibulk request — `*<argc>\r\n` followed by `argc`
+/// `$<len>\r\n<len bytes>\r\n` bulk strings — into its raw argument
+/// tokens, the same shape `tokenize` returns for the inline format. Every
+/// bulk string is read by its declared length rather than scanned for a
+/// delimiter, so embedded spaces, CRLFs, and NUL bytes come through
+/// untouched.
+named!(pub parse_multibulk<Vec<&[u8]> >,
+    chain!(
+        tag!("*") ~
+        argc: bounded_argc ~
+        crlf ~
+        tokens: count!(preceded!(tag!("$"), bulk_string), argc),
+        || tokens
+    )
+);
+
+/// Entry point for a client request of either shape: a leading `*` marks
+/// RESP multibulk encoding (what real Redis clients send), anything else
+/// falls back to the inline `tokenize` parser (telnet-style input).
+/// `parse_multibulk`'s tokens are plain slices of the input, so they're
+/// copied to line up with `tokenize`'s owned `Vec<Vec<u8>>`.
+pub fn parse_command(input: &[u8]) -> IResult<&[u8], Vec<Vec<u8>>> {
+    match input.first() {
+        Some(&b'*') => match parse_multibulk(input) {
+            IResult::Done(rest, tokens) =>
+                IResult::Done(rest, tokens.iter().map(|t| t.to_vec()).collect()),
+            IResult::Error(e) => IResult::Error(e),
+            IResult::Incomplete(n) => IResult::Incomplete(n),
+        },
+        _ => {
+            // `multispace` between tokens also matches CR/LF, so `tokenize` only
+            // sees the first line rather than running on into the next request.
+            let end = match input.windows(2).position(|w| w == b"\r\n") {
+                Some(i) => i + 2,
+                None => return tokenize(input),
+            };
+            match tokenize(&input[..end]) {
+                IResult::Done(_, tokens) => IResult::Done(&input[end..], tokens),
+                IResult::Error(Err::Position(kind, pos)) => IResult::Error(Err::Position(kind, &input[end - pos.len()..])),
+                IResult::Incomplete(_) => IResult::Error(Err::Position(ErrorKind::Tag, &input[end - 2..])),
+                other => other,
+            }
+        }
+    }
+}
+
+/// Why `tokenize_request` rejected a buffer as malformed, as opposed to
+/// merely incomplete. Not every nom-level failure can be traced back to
+/// a specific cause of our own — `tag!`/`digit`/friends report a bare
+/// built-in `ErrorKind` for things like a missing CRLF or a bad bulk
+/// length — so those fall back to `Malformed` rather than guessing.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Reason {
+    /// A `\xHH` escape inside a double-quoted token wasn't followed by
+    /// two hex digits.
+    BadHexEscape,
+    /// A quoted token's closing quote was immediately followed by
+    /// something other than whitespace or end-of-input.
+    TrailingGarbageAfterQuote,
+    /// A missing CRLF, a bad bulk length, or anything else nom's own
+    /// combinators rejected without a cause we track separately.
+    Malformed,
+}
+
+fn reason_for(kind: ErrorKind<u32>) -> Reason {
+    match kind {
+        ErrorKind::Custom(BAD_HEX_ESCAPE) => Reason::BadHexEscape,
+        ErrorKind::Custom(TRAILING_GARBAGE_AFTER_QUOTE) => Reason::TrailingGarbageAfterQuote,
+        _ => Reason::Malformed,
+    }
+}
+
+/// Outcome of `tokenize_request` other than a full parse: distinct from
+/// nom's own `IResult` so a connection loop gets a plain two-way split
+/// instead of having to know nom's conventions. `Incomplete` covers both
+/// an inline request missing its trailing CRLF and a RESP bulk string
+/// short of its declared length — in either case the buffer may simply
+/// not have all its bytes yet, and the caller should read more and retry
+/// rather than treat it as malformed. `Invalid` carries the byte offset
+/// into the input where parsing gave up and, where we can tell, why.
+#[derive(Debug, PartialEq, Eq)]
+pub enum TokenizeError {
+    Incomplete,
+    Invalid { offset: usize, reason: Reason },
+}
+
+/// Parses one request (inline or RESP multibulk, same dispatch as
+/// `parse_command`) and reports how many leading bytes of `input` it
+/// consumed, so a connection loop can drain exactly that much off its
+/// buffer and retry on whatever is left over.
+pub fn tokenize_request(input: &[u8]) -> Result<(Vec<Vec<u8>>, usize), TokenizeError> {
+    match parse_command(input) {
+        IResult::Done(rest, tokens) => Ok((tokens, input.len() - rest.len())),
+        IResult::Incomplete(_) => Err(TokenizeError::Incomplete),
+        IResult::Error(Err::Position(kind, pos)) => Err(TokenizeError::Invalid {
+            offset: input.len() - pos.len(),
+            reason: reason_for(kind),
+        }),
+        IResult::Error(_) => Err(TokenizeError::Invalid { offset: 0, reason: Reason::Malformed }),
+    }
+}
+
 #[cfg(test)]
 mod test {
     use nom::IResult;
-    use super::tokenize;
+    use super::{parse_command, parse_multibulk, tokenize, tokenize_request, Reason, TokenizeError};
     use redis::commands::Bytes;
 
     #[test]
@@ -35,13 +281,214 @@ mod test {
         tokenizes_to(&[b"set", b"foo", b"bar"], b"set \"foo\" \"bar\"\r\n");
     }
 
+    #[test]
+    fn double_quoted_decodes_escapes() {
+        tokenizes_to(&[b"foo\nbar"], b"\"foo\\x0abar\"\r\n");
+    }
+
+    #[test]
+    fn single_quoted_only_escapes_the_quote_character() {
+        tokenizes_to(&[b"it's"], b"'it\\'s'\r\n");
+    }
+
+    #[test]
+    fn unterminated_quote_is_incomplete() {
+        match tokenize(b"\"foo") {
+            IResult::Incomplete(_) => (),
+            other => panic!(format!("{:?}", other)),
+        }
+    }
+
+    #[test]
+    fn closing_quote_must_be_followed_by_whitespace() {
+        assert!(tokenize(b"\"foo\"bar\r\n").is_err());
+    }
+
     fn tokenizes_to(expected: &[Bytes], i: Bytes) {
         let actual = tokenize(i);
 
+        if let IResult::Done(&[], tokenized) = actual {
+            let borrowed: Vec<&[u8]> = tokenized.iter().map(Vec::as_slice).collect();
+            assert_eq!(expected, borrowed.as_slice());
+        } else {
+            panic!(format!("{:?}", actual));
+        }
+    }
+
+    #[test]
+    fn multibulk_example() {
+        multibulk_parses_to(
+            &[b"set", b"foo", b"bar"],
+            b"*3\r\n$3\r\nset\r\n$3\r\nfoo\r\n$3\r\nbar\r\n"
+        );
+    }
+
+    #[test]
+    fn multibulk_preserves_embedded_spaces_crlf_and_nul() {
+        multibulk_parses_to(
+            &[b"set", b"foo", b"a b\r\n\x00c"],
+            b"*3\r\n$3\r\nset\r\n$3\r\nfoo\r\n$7\r\na b\r\n\x00c\r\n"
+        );
+    }
+
+    #[test]
+    fn multibulk_rejects_negative_length() {
+        assert!(parse_multibulk(b"*1\r\n$-1\r\n\r\n").is_err());
+    }
+
+    #[test]
+    fn multibulk_rejects_overflowing_length() {
+        assert!(parse_multibulk(b"*1\r\n$99999999999999999999\r\nx\r\n").is_err());
+    }
+
+    #[test]
+    fn multibulk_rejects_missing_trailing_crlf() {
+        assert!(parse_multibulk(b"*1\r\n$3\r\nfoobar\r\n").is_err());
+    }
+
+    #[test]
+    fn multibulk_rejects_an_absurd_argc_instead_of_allocating_for_it() {
+        // Well within `usize`, so `size` accepts it; `count!` must never
+        // see it, or its `Vec::with_capacity(argc)` panics the process.
+        assert!(parse_multibulk(b"*600000000000000000\r\n").is_err());
+    }
+
+    fn multibulk_parses_to(expected: &[Bytes], i: Bytes) {
+        let actual = parse_multibulk(i);
+
         if let IResult::Done(&[], tokenized) = actual {
             assert_eq!(expected, tokenized.as_slice());
         } else {
             panic!(format!("{:?}", actual));
         }
     }
+
+    #[test]
+    fn parse_command_dispatches_on_leading_byte() {
+        tokenizes_with(parse_command, &[b"set", b"foo", b"bar"], b"set foo bar\r\n");
+        tokenizes_with(
+            parse_command,
+            &[b"set", b"foo", b"bar"],
+            b"*3\r\n$3\r\nset\r\n$3\r\nfoo\r\n$3\r\nbar\r\n"
+        );
+    }
+
+    fn tokenizes_with<F>(f: F, expected: &[Bytes], i: Bytes)
+        where F: Fn(Bytes) -> IResult<&[u8], Vec<Vec<u8>>>
+    {
+        let actual = f(i);
+
+        if let IResult::Done(&[], tokenized) = actual {
+            let borrowed: Vec<&[u8]> = tokenized.iter().map(Vec::as_slice).collect();
+            assert_eq!(expected, borrowed.as_slice());
+        } else {
+            panic!(format!("{:?}", actual));
+        }
+    }
+
+    #[test]
+    fn tokenize_request_reports_bytes_consumed() {
+        let (tokens, consumed) = tokenize_request(b"set foo bar\r\nGET foo\r\n").unwrap();
+
+        assert_eq!(vec![b"set".to_vec(), b"foo".to_vec(), b"bar".to_vec()], tokens);
+        assert_eq!("set foo bar\r\n".len(), consumed);
+    }
+
+    #[test]
+    fn tokenize_request_is_incomplete_without_a_trailing_crlf() {
+        assert_eq!(Err(TokenizeError::Incomplete), tokenize_request(b"set foo bar"));
+    }
+
+    #[test]
+    fn tokenize_request_is_incomplete_short_of_a_declared_bulk_length() {
+        assert_eq!(
+            Err(TokenizeError::Incomplete),
+            tokenize_request(b"*2\r\n$3\r\nset\r\n$3\r\nfo")
+        );
+    }
+
+    #[test]
+    fn tokenize_request_reports_offset_and_reason_for_trailing_garbage() {
+        assert_eq!(
+            Err(TokenizeError::Invalid { offset: 5, reason: Reason::TrailingGarbageAfterQuote }),
+            tokenize_request(b"\"foo\"bar\r\n")
+        );
+    }
+
+    #[test]
+    fn tokenize_request_reports_offset_and_reason_for_a_bad_hex_escape() {
+        assert_eq!(
+            Err(TokenizeError::Invalid { offset: 1, reason: Reason::BadHexEscape }),
+            tokenize_request(b"\"\\xzz\"\r\n")
+        );
+    }
+
+    // Always double-quotes and escapes every byte that isn't a plain
+    // printable ASCII character, so the result round-trips through
+    // `tokenize` regardless of what `token` contains — embedded spaces,
+    // quotes, backslashes, NUL, all of it.
+    fn encode_inline_token(token: &[u8]) -> Vec<u8> {
+        let mut out = vec![b'"'];
+
+        for &b in token {
+            match b {
+                b'"' => out.extend_from_slice(b"\\\""),
+                b'\\' => out.extend_from_slice(b"\\\\"),
+                b'\n' => out.extend_from_slice(b"\\n"),
+                b'\r' => out.extend_from_slice(b"\\r"),
+                b'\t' => out.extend_from_slice(b"\\t"),
+                0x08 => out.extend_from_slice(b"\\b"),
+                0x07 => out.extend_from_slice(b"\\a"),
+                _ if b < 0x20 || b >= 0x7f => {
+                    out.extend_from_slice(format!("\\x{:02x}", b).as_bytes());
+                }
+                _ => out.push(b),
+            }
+        }
+
+        out.push(b'"');
+        out
+    }
+
+    fn encode_inline(tokens: &[Vec<u8>]) -> Vec<u8> {
+        let mut out = Vec::new();
+
+        for (i, token) in tokens.iter().enumerate() {
+            if i > 0 {
+                out.push(b' ');
+            }
+            out.extend(encode_inline_token(token));
+        }
+
+        out.extend_from_slice(b"\r\n");
+        out
+    }
+
+    fn encode_multibulk(tokens: &[Vec<u8>]) -> Vec<u8> {
+        let mut out = format!("*{}\r\n", tokens.len()).into_bytes();
+
+        for token in tokens {
+            out.extend(format!("${}\r\n", token.len()).into_bytes());
+            out.extend_from_slice(token);
+            out.extend_from_slice(b"\r\n");
+        }
+
+        out
+    }
+
+    fn parses_to(input: &[u8], expected: &[Vec<u8>]) -> bool {
+        match parse_command(input) {
+            IResult::Done(&[], tokens) => tokens == expected,
+            _ => false,
+        }
+    }
+
+    #[quickcheck]
+    fn inline_and_multibulk_round_trip_the_same_arguments(tokens: Vec<Vec<u8>>) -> bool {
+        if tokens.is_empty() {
+            return true;
+        }
+
+        parses_to(&encode_inline(&tokens), &tokens) && parses_to(&encode_multibulk(&tokens), &tokens)
+    }
 }
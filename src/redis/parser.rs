@@ -1,5 +1,5 @@
 use nom::{multispace, digit, alpha};
-use redis::commands::{Command, IntRange};
+use redis::commands::{parse_options, Command, IntRange};
 use std::str;
 
 fn not_multispace(c: u8) -> bool {
@@ -49,6 +49,16 @@ named!(key_value<(&[u8], &[u8])>,
     )
 );
 
+named!(set_args<(&[u8], &[u8], Vec<&[u8]>)>,
+    chain!(
+        key: string ~
+        multispace ~
+        value: string ~
+        rest: many0!(preceded!(call!(::nom::space), take_while1!(not_multispace))),
+        || (key, value, rest)
+    )
+);
+
 named!(key_values<(&[u8], Vec<&[u8]>)>,
     chain!(
         key: string ~
@@ -101,7 +111,9 @@ named!(pub parse<Command>,
           | b"GETRANGE" => map!(key_range, |(k, r)| Command::GetRange { key: k, range: r })
           | b"INCRBY"   => map!(key_int, |(k, by)| Command::IncrBy { key: k, by: by })
           | b"DECRBY"   => map!(key_int, |(k, by)| Command::DecrBy { key: k, by: by })
-          | b"SET"      => map!(key_value, |(k, v)| Command::Set { key: k, value: v })
+          | b"SET"      => map_res!(set_args, |(k, v, rest): (&[u8], &[u8], Vec<&[u8]>)| {
+                parse_options(b"set", &rest).map(|options| Command::Set { key: k, value: v, options: options })
+            })
           | b"APPEND"   => map!(key_value, |(k, v)| Command::Append { key: k, value: v })
           | b"RENAME"   => map!(key_value, |(k1, k2)| Command::Rename { key: k1, new_key: k2 })
           | b"LPUSH"    => map!(key_values, |(k, vs)| Command::LPush { key: k, values: vs })
@@ -116,7 +128,7 @@ named!(pub parse<Command>,
 #[cfg(test)]
 mod test {
     use nom::IResult;
-    use redis::commands::Command;
+    use redis::commands::{Command, SetOptions};
     use super::parse;
 
     #[test]
@@ -145,14 +157,14 @@ mod test {
 
     #[test]
     fn set_empty() {
-        let empty = Command::Set { key: b"", value: b"" };
+        let empty = Command::Set { key: b"", value: b"", options: SetOptions::default() };
 
         parses_to("SET \"\" \"\"\n", &empty);
     }
 
     #[test]
     fn set_ascii() {
-        let foo = Command::Set { key: b"foo", value: b"bar" };
+        let foo = Command::Set { key: b"foo", value: b"bar", options: SetOptions::default() };
 
         parses_to("SET foo   bar \n", &foo);
         parses_to("SET \"foo\" bar \n", &foo);
@@ -162,11 +174,28 @@ mod test {
 
     #[test]
     fn set_bytes() {
-        let bytes = Command::Set { key: b"\x01\x02\x03", value: b"\x01\x02\x03" };
+        let bytes = Command::Set { key: b"\x01\x02\x03", value: b"\x01\x02\x03", options: SetOptions::default() };
         parses_to("SET \"\x01\x02\x03\" \"\x01\x02\x03\" \n", &bytes);
         parses_to("SET \x01\x02\x03  \x01\x02\x03 \n", &bytes);
     }
 
+    #[test]
+    fn set_with_options() {
+        use redis::commands::{Existence, Expiry};
+
+        let cmd = Command::Set {
+            key: b"foo",
+            value: b"bar",
+            options: SetOptions {
+                expire: Some(Expiry::Seconds(10)),
+                existence: Some(Existence::Nx),
+                keep_ttl: false,
+            },
+        };
+
+        parses_to("SET foo bar EX 10 NX", &cmd);
+    }
+
     #[test]
     fn exists() {
         let cmd = Command::Exists { keys: vec!(b"foo", b"bar") };
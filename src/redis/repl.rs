@@ -1,33 +1,147 @@
-use nom::IResult;
+use nom::{Err, IResult};
 use redis::database::Database;
 use redis::parser::parse;
 use redis::resp::encode;
-use std::io;
+use std::io::{self, Read, Write};
+
+const BUFFER_SIZE: usize = 4096;
 
 pub fn repl() {
-    let input = io::stdin();
     let mut database = Database::new();
+    drive(&mut database, io::stdin(), io::stdout());
+}
+
+/// Drives a single connection end-to-end. Unlike a one-line-at-a-time read
+/// loop, this accumulates raw bytes into a growable buffer and drains as
+/// many complete commands as it holds before blocking on another read —
+/// so pipelined commands that arrive in one `read` run back-to-back, and a
+/// command whose bytes are split across reads just waits for the rest
+/// instead of being dropped.
+pub fn drive<R: Read, W: Write>(database: &mut Database, mut r: R, mut w: W) {
+    let mut buffer = Vec::new();
+    let mut chunk = [0; BUFFER_SIZE];
 
     loop {
-        let mut output = io::stdout();
+        loop {
+            match parse(&buffer) {
+                IResult::Done(rest, cmd) => {
+                    let consumed = buffer.len() - rest.len();
+                    let res = database.apply(cmd);
+                    encode(&res, ::redis::resp::Protocol::Resp2, &mut w).unwrap();
+                    buffer.drain(..consumed);
+                }
+                IResult::Incomplete(_) =>
+                    break,
+                IResult::Error(err) => {
+                    write_protocol_error(&mut w, offset_of(&err, &buffer));
+                    buffer.clear();
+                    break;
+                }
+            }
+        }
 
-        let mut line = String::new();
-        match input.read_line(&mut line) {
+        match r.read(&mut chunk) {
             Ok(0) => break,
-            Ok(_) => {
-                match parse(line.as_bytes()) {
-                    IResult::Done(_, cmd) => {
-                        let res = database.apply(cmd);
-                        encode(&res, &mut output).unwrap();
-                    }
-                    IResult::Error(err) =>
-                        println!("Error: {:?}", err),
-                    IResult::Incomplete(needed) =>
-                        println!("Incomplete: {:?}", needed),
+            Ok(n) => buffer.extend_from_slice(&chunk[..n]),
+            Err(error) => panic!("{:?}", error),
+        }
+    }
+}
+
+fn write_protocol_error<W: Write>(w: &mut W, offset: usize) {
+    write!(w, "-ERR Protocol error at byte {}\r\n", offset).unwrap();
+}
+
+// `parser::parse` reports failures as nom's own `Err::Position`, which
+// carries the unparsed tail rather than an offset; this just turns that
+// back into a byte position within `buffer` for the error message.
+fn offset_of(err: &Err<&[u8], u32>, buffer: &[u8]) -> usize {
+    match *err {
+        Err::Position(_, pos) => buffer.len() - pos.len(),
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use redis::database::Database;
+    use std::io::{self, Cursor, Read};
+    use super::drive;
+
+    #[test]
+    fn pipelined_commands_run_back_to_back() {
+        let mut database = Database::new();
+        let mut output = Vec::new();
+
+        drive(
+            &mut database,
+            Cursor::new(b"SET foo bar\nGET foo\n".to_vec()),
+            &mut output
+        );
+
+        assert_eq!(
+            b"+OK\r\n$3\r\nbar\r\n".to_vec(),
+            output
+        );
+    }
+
+    #[test]
+    fn command_split_across_reads_is_not_dropped() {
+        let mut database = Database::new();
+        let mut output = Vec::new();
+
+        drive(
+            &mut database,
+            ChunkedReader::new(vec![b"SET fo".to_vec(), b"o bar\n".to_vec()]),
+            &mut output
+        );
+
+        assert_eq!(b"+OK\r\n".to_vec(), output);
+    }
+
+    #[test]
+    fn protocol_error_resynchronizes_instead_of_stopping() {
+        let mut database = Database::new();
+        let mut output = Vec::new();
+
+        // The malformed line and everything already buffered alongside it
+        // are dropped together; a command arriving on the next read still
+        // goes through.
+        drive(
+            &mut database,
+            ChunkedReader::new(vec![b"\x01\x02\x03\n".to_vec(), b"GET foo\n".to_vec()]),
+            &mut output
+        );
+
+        assert_eq!(
+            b"-ERR Protocol error at byte 0\r\n$-1\r\n".to_vec(),
+            output
+        );
+    }
+
+    // Hands back one pre-scripted chunk per `read()` call, to exercise a
+    // command whose bytes are split across multiple reads.
+    struct ChunkedReader {
+        chunks: Vec<Vec<u8>>,
+    }
+
+    impl ChunkedReader {
+        fn new(mut chunks: Vec<Vec<u8>>) -> ChunkedReader {
+            chunks.reverse();
+            ChunkedReader { chunks: chunks }
+        }
+    }
+
+    impl Read for ChunkedReader {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            match self.chunks.pop() {
+                Some(chunk) => {
+                    let len = chunk.len();
+                    buf[..len].copy_from_slice(&chunk);
+                    Ok(len)
                 }
-            },
-            Err(error) =>
-                panic!("{:?}", error),
+                None => Ok(0),
+            }
         }
     }
 }
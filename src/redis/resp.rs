@@ -1,10 +1,26 @@
-use nom::{crlf, digit};
+use nom::{crlf, digit, Err, ErrorKind, IResult};
 use redis::commands::Bytes;
 use redis::database::{CommandError, CommandReturn, CommandResult, Type};
+use std::f64;
 use std::io::{self, Write};
 use std::str::{self, FromStr};
 
-#[derive(Debug, PartialEq, Eq, Clone)]
+/// Which RESP wire format a connection has negotiated via `HELLO`. Threaded
+/// through `encode()` so RESP3's richer types (maps, sets, doubles, ...)
+/// downgrade to their RESP2 equivalents when the client hasn't opted in.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum Protocol {
+    Resp2,
+    Resp3,
+}
+
+impl Default for Protocol {
+    fn default() -> Protocol { Protocol::Resp2 }
+}
+
+// PartialEq only: `Double(f64)` keeps the RESP3 type taxonomy honest but
+// rules out `Eq`, same tradeoff `Command` already made for `IncrByFloat`.
+#[derive(Debug, PartialEq, Clone)]
 pub enum Value<'a> {
     SimpleString(Bytes<'a>),
     Error(Bytes<'a>),
@@ -12,21 +28,31 @@ pub enum Value<'a> {
     BulkString(Bytes<'a>),
     Array(Vec<Value<'a>>),
     Null,
+    Double(f64),
+    Boolean(bool),
+    BigNumber(Bytes<'a>),
+    VerbatimString { format: [u8; 3], data: Bytes<'a> },
+    Map(Vec<(Value<'a>, Value<'a>)>),
+    Set(Vec<Value<'a>>),
+    Push(Vec<Value<'a>>),
 }
 
-pub fn encode<T: Write>(result: &CommandResult, w: &mut T) -> io::Result<()> {
+pub fn encode<T: Write>(result: &CommandResult, protocol: Protocol, w: &mut T) -> io::Result<()> {
     match *result {
-        Ok(ref ret)  => encode_return(ret, w),
+        Ok(ref ret)  => encode_return(ret, protocol, w),
         Err(ref err) => encode_error(err, w),
     }
 }
 
-fn encode_return<T: Write>(ret: &CommandReturn, w: &mut T) -> io::Result<()> {
+fn encode_return<T: Write>(ret: &CommandReturn, protocol: Protocol, w: &mut T) -> io::Result<()> {
     match *ret {
         CommandReturn::Ok =>
             try!(write!(w, "+OK\r\n")),
         CommandReturn::Nil =>
-            try!(write!(w, "$-1\r\n")),
+            match protocol {
+                Protocol::Resp3 => try!(write!(w, "_\r\n")),
+                Protocol::Resp2 => try!(write!(w, "$-1\r\n")),
+            },
         CommandReturn::BulkString(ref s) => {
             try!(write!(w, "${}\r\n", s.len()));
             try!(w.write_all(s));
@@ -42,18 +68,111 @@ fn encode_return<T: Write>(ret: &CommandReturn, w: &mut T) -> io::Result<()> {
             try!(write!(w, "+string\r\n")),
         CommandReturn::Type(Type::List) =>
             try!(write!(w, "+list\r\n")),
+        CommandReturn::Type(Type::Hash) =>
+            try!(write!(w, "+hash\r\n")),
         CommandReturn::Array(ref v) => {
             try!(write!(w, "*{}\r\n", v.len()));
 
             for m in v {
-                try!(encode_return(m, w));
+                try!(encode_return(m, protocol, w));
             }
         }
+        CommandReturn::Double(f) =>
+            match protocol {
+                Protocol::Resp3 =>
+                    try!(write!(w, ",{}\r\n", format_double(f))),
+                Protocol::Resp2 => {
+                    let s = format_double(f);
+                    try!(write!(w, "${}\r\n{}\r\n", s.len(), s));
+                }
+            },
+        CommandReturn::Boolean(b) =>
+            match protocol {
+                Protocol::Resp3 => try!(write!(w, "#{}\r\n", if b { "t" } else { "f" })),
+                Protocol::Resp2 => try!(write!(w, ":{}\r\n", if b { 1 } else { 0 })),
+            },
+        CommandReturn::BigNumber(ref n) =>
+            match protocol {
+                Protocol::Resp3 => {
+                    try!(write!(w, "("));
+                    try!(w.write_all(n));
+                    try!(write!(w, "\r\n"));
+                }
+                Protocol::Resp2 => {
+                    try!(write!(w, "${}\r\n", n.len()));
+                    try!(w.write_all(n));
+                    try!(write!(w, "\r\n"));
+                }
+            },
+        CommandReturn::VerbatimString { ref format, ref data } =>
+            match protocol {
+                Protocol::Resp3 => {
+                    try!(write!(w, "={}\r\n", data.len() + 4));
+                    try!(w.write_all(format));
+                    try!(write!(w, ":"));
+                    try!(w.write_all(data));
+                    try!(write!(w, "\r\n"));
+                }
+                Protocol::Resp2 => {
+                    try!(write!(w, "${}\r\n", data.len()));
+                    try!(w.write_all(data));
+                    try!(write!(w, "\r\n"));
+                }
+            },
+        CommandReturn::Map(ref pairs) => {
+            match protocol {
+                Protocol::Resp3 => try!(write!(w, "%{}\r\n", pairs.len())),
+                Protocol::Resp2 => try!(write!(w, "*{}\r\n", pairs.len() * 2)),
+            }
+
+            for &(ref key, ref value) in pairs {
+                try!(encode_return(key, protocol, w));
+                try!(encode_return(value, protocol, w));
+            }
+        }
+        CommandReturn::Set(ref items) => {
+            match protocol {
+                Protocol::Resp3 => try!(write!(w, "~{}\r\n", items.len())),
+                Protocol::Resp2 => try!(write!(w, "*{}\r\n", items.len())),
+            }
+
+            for item in items {
+                try!(encode_return(item, protocol, w));
+            }
+        }
+        CommandReturn::Push(ref items) => {
+            match protocol {
+                Protocol::Resp3 => try!(write!(w, ">{}\r\n", items.len())),
+                Protocol::Resp2 => try!(write!(w, "*{}\r\n", items.len())),
+            }
+
+            for item in items {
+                try!(encode_return(item, protocol, w));
+            }
+        }
+        CommandReturn::Queued =>
+            try!(write!(w, "+QUEUED\r\n")),
+        CommandReturn::Error(ref err) =>
+            try!(encode_error(err, w)),
     }
 
     Ok(())
 }
 
+// Redis spells the non-finite doubles lowercase (`inf`, `-inf`, `nan`),
+// unlike Rust's `Display` impl (`inf`, `-inf`, `NaN`).
+fn format_double(f: f64) -> String {
+    if f.is_nan() {
+        "nan".to_string()
+    } else if f == f64::INFINITY {
+        "inf".to_string()
+    } else if f == f64::NEG_INFINITY {
+        "-inf".to_string()
+    } else {
+        format!("{}", f)
+    }
+}
+
 fn encode_error<T: Write>(err: &CommandError, w: &mut T) -> io::Result<()> {
     try!(write!(w, "-"));
 
@@ -74,8 +193,39 @@ fn encode_error<T: Write>(err: &CommandError, w: &mut T) -> io::Result<()> {
         }
         CommandError::NotAnInteger =>
             try!(write!(w, "ERR value is not an integer or out of range")),
+        CommandError::NotAFloat =>
+            try!(write!(w, "ERR value is not a valid float")),
         CommandError::IntegerOverflow =>
             try!(write!(w, "ERR increment or decrement would overflow")),
+        CommandError::BadCommandSyntax(ref cmd) => {
+            try!(write!(w, "ERR syntax error in '"));
+            try!(w.write_all(cmd));
+            try!(write!(w, "'"));
+        }
+        CommandError::InvalidDumpPayload =>
+            try!(write!(w, "ERR Bad data format")),
+        CommandError::DumpChecksumMismatch =>
+            try!(write!(w, "ERR DUMP payload version or checksum are wrong")),
+        CommandError::InvalidSnapshot =>
+            try!(write!(w, "ERR Bad snapshot data")),
+        CommandError::StringExceedsMaxSize =>
+            try!(write!(w, "ERR string exceeds maximum allowed size (proto-max-bulk-len)")),
+        CommandError::InvalidCursor =>
+            try!(write!(w, "ERR invalid cursor")),
+        CommandError::UnsupportedProtocol =>
+            try!(write!(w, "NOPROTO unsupported protocol version")),
+        CommandError::StoreFull =>
+            try!(write!(w, "ERR store is at capacity")),
+        CommandError::OutOfRange =>
+            try!(write!(w, "ERR index out of range")),
+        CommandError::ExecAbort =>
+            try!(write!(w, "EXECABORT Transaction discarded because of previous errors.")),
+        CommandError::ExecWithoutMulti =>
+            try!(write!(w, "ERR EXEC without MULTI")),
+        CommandError::DiscardWithoutMulti =>
+            try!(write!(w, "ERR DISCARD without MULTI")),
+        CommandError::NestedMulti =>
+            try!(write!(w, "ERR MULTI calls can not be nested")),
     }
 
     write!(w, "\r\n")
@@ -102,6 +252,27 @@ named!(size<usize>,
     )
 );
 
+// Same hazard as `line.rs::bounded_argc`: `array`/`map_value` below feed
+// their declared element count straight into `count!`, whose
+// `Vec::with_capacity` panics outright once the count overflows
+// `isize::MAX` worth of elements — a declared size well within `usize`
+// is enough to trigger it, so it must be bounded before `count!` ever
+// sees it.
+const MAX_COLLECTION_LEN: usize = 1024 * 1024;
+
+fn bounded_size(input: &[u8]) -> IResult<&[u8], usize> {
+    match size(input) {
+        IResult::Done(rest, n) => {
+            if n > MAX_COLLECTION_LEN {
+                IResult::Error(Err::Position(ErrorKind::Custom(0), input))
+            } else {
+                IResult::Done(rest, n)
+            }
+        }
+        other => other,
+    }
+}
+
 named!(bulk_string,
     chain!(
         size: size ~
@@ -121,7 +292,7 @@ named!(binary_string,
 
 named!(array<Vec<Value> >,
     chain!(
-        size: size ~
+        size: bounded_size ~
         crlf ~
         values: count!(decode, size),
         || values
@@ -130,6 +301,56 @@ named!(array<Vec<Value> >,
 
 named!(null, tag!(b"-1\r\n"));
 
+// Rust's own `f64::from_str` already accepts the bare tokens "inf", "-inf"
+// and "nan" (case-insensitively), so the RESP3 double edge cases fall out
+// of reusing `binary_string` without any special-casing.
+named!(double<f64>,
+    map_res!(
+        map_res!(binary_string, str::from_utf8),
+        FromStr::from_str
+    )
+);
+
+named!(boolean<bool>,
+    alt!(
+        map!(tag!("t\r\n"), |_| true)
+      | map!(tag!("f\r\n"), |_| false)
+    )
+);
+
+named!(verbatim_string<Value>,
+    chain!(
+        len: size ~
+        crlf ~
+        format: take!(3) ~
+        tag!(":") ~
+        data: take!(len.saturating_sub(4)) ~
+        crlf,
+        || {
+            let mut fmt = [0u8; 3];
+            fmt.copy_from_slice(format);
+            Value::VerbatimString { format: fmt, data: data }
+        }
+    )
+);
+
+named!(kv_pair<(Value, Value)>,
+    chain!(
+        key: decode ~
+        value: decode,
+        || (key, value)
+    )
+);
+
+named!(map_value<Vec<(Value, Value)> >,
+    chain!(
+        size: bounded_size ~
+        crlf ~
+        pairs: count!(kv_pair, size),
+        || pairs
+    )
+);
+
 named!(pub decode<Value>,
     switch!(take!(1),
         b"+" => map!(binary_string, Value::SimpleString)
@@ -143,6 +364,14 @@ named!(pub decode<Value>,
                     map!(null, |_| Value::Null)
                   | map!(array, Value::Array)
                 )
+      | b"_" => map!(crlf, |_| Value::Null)
+      | b"," => map!(double, Value::Double)
+      | b"#" => map!(boolean, Value::Boolean)
+      | b"(" => map!(binary_string, Value::BigNumber)
+      | b"=" => call!(verbatim_string)
+      | b"%" => map!(map_value, Value::Map)
+      | b"~" => map!(array, Value::Set)
+      | b">" => map!(array, Value::Push)
     )
 );
 
@@ -244,6 +473,19 @@ mod test {
             );
         }
 
+        #[test]
+        fn array_rejects_an_absurd_declared_size_instead_of_allocating_for_it() {
+            // Well within `usize`, so `size` accepts it; `count!` must
+            // never see it, or its `Vec::with_capacity(size)` panics the
+            // process.
+            doesnt_parse(b"*600000000000000000\r\n");
+        }
+
+        #[test]
+        fn map_rejects_an_absurd_declared_size_instead_of_allocating_for_it() {
+            doesnt_parse(b"%600000000000000000\r\n");
+        }
+
         fn parses_to(i: &[u8], v: &Value) {
             assert_eq!(
                 IResult::Done(&b""[..], v.clone()),
@@ -256,12 +498,93 @@ mod test {
             println!("{:?}", result);
             assert!(result.is_err());
         }
+
+        #[test]
+        fn null() {
+            parses_to(b"_\r\n", &Value::Null);
+        }
+
+        #[quickcheck]
+        fn doubles(n: f64) -> TestResult {
+            if n.is_nan() {
+                return TestResult::discard();
+            }
+
+            parses_to(
+                format!(",{}\r\n", n).as_bytes(),
+                &Value::Double(n)
+            );
+
+            TestResult::passed()
+        }
+
+        #[test]
+        fn double_non_finite() {
+            parses_to(b",inf\r\n", &Value::Double(::std::f64::INFINITY));
+            parses_to(b",-inf\r\n", &Value::Double(::std::f64::NEG_INFINITY));
+            assert!(match decode(b",nan\r\n") {
+                IResult::Done(&b""[..], Value::Double(n)) => n.is_nan(),
+                _ => false,
+            });
+        }
+
+        #[test]
+        fn booleans() {
+            parses_to(b"#t\r\n", &Value::Boolean(true));
+            parses_to(b"#f\r\n", &Value::Boolean(false));
+        }
+
+        #[test]
+        fn big_number() {
+            parses_to(
+                b"(3492890328409238509324850943850943825024385\r\n",
+                &Value::BigNumber(b"3492890328409238509324850943850943825024385")
+            );
+        }
+
+        #[test]
+        fn verbatim_string() {
+            parses_to(
+                b"=15\r\ntxt:Some string\r\n",
+                &Value::VerbatimString { format: *b"txt", data: b"Some string" }
+            );
+        }
+
+        #[test]
+        fn map() {
+            parses_to(b"%0\r\n", &Value::Map(vec![]));
+            parses_to(
+                b"%2\r\n+first\r\n:1\r\n+second\r\n:2\r\n",
+                &Value::Map(vec![
+                    (Value::SimpleString(b"first"), Value::Integer(1)),
+                    (Value::SimpleString(b"second"), Value::Integer(2)),
+                ])
+            );
+        }
+
+        #[test]
+        fn set() {
+            parses_to(b"~0\r\n", &Value::Set(vec![]));
+            parses_to(
+                b"~2\r\n:1\r\n:2\r\n",
+                &Value::Set(vec![Value::Integer(1), Value::Integer(2)])
+            );
+        }
+
+        #[test]
+        fn push() {
+            parses_to(b">0\r\n", &Value::Push(vec![]));
+            parses_to(
+                b">1\r\n+message\r\n",
+                &Value::Push(vec![Value::SimpleString(b"message")])
+            );
+        }
     }
 
     mod encode {
         use redis::database::{CommandError, CommandReturn, CommandResult, Type};
         use std::borrow::Cow;
-        use super::super::{encode};
+        use super::super::{encode, Protocol};
 
         #[test]
         fn ok() {
@@ -273,6 +596,11 @@ mod test {
             encodes_to(Ok(CommandReturn::Nil), "$-1\r\n");
         }
 
+        #[test]
+        fn nil_resp3() {
+            encodes_to_protocol(Ok(CommandReturn::Nil), Protocol::Resp3, "_\r\n");
+        }
+
         #[test]
         fn bulk_string() {
             encodes_to(
@@ -368,10 +696,205 @@ mod test {
             );
         }
 
+        #[test]
+        fn not_a_float() {
+            encodes_to(
+                Err(CommandError::NotAFloat),
+                "-ERR value is not a valid float\r\n"
+            );
+        }
+
+        #[test]
+        fn bad_command_syntax() {
+            encodes_to(
+                Err(CommandError::BadCommandSyntax(b"set".to_vec())),
+                "-ERR syntax error in 'set'\r\n"
+            );
+        }
+
+        #[test]
+        fn invalid_dump_payload() {
+            encodes_to(
+                Err(CommandError::InvalidDumpPayload),
+                "-ERR Bad data format\r\n"
+            );
+        }
+
+        #[test]
+        fn unsupported_protocol() {
+            encodes_to(
+                Err(CommandError::UnsupportedProtocol),
+                "-NOPROTO unsupported protocol version\r\n"
+            );
+        }
+
+        #[test]
+        fn out_of_range() {
+            encodes_to(
+                Err(CommandError::OutOfRange),
+                "-ERR index out of range\r\n"
+            );
+        }
+
+        #[test]
+        fn double() {
+            encodes_to_protocol(Ok(CommandReturn::Double(3.14)), Protocol::Resp2, "$4\r\n3.14\r\n");
+            encodes_to_protocol(Ok(CommandReturn::Double(3.14)), Protocol::Resp3, ",3.14\r\n");
+        }
+
+        #[test]
+        fn double_non_finite() {
+            encodes_to_protocol(
+                Ok(CommandReturn::Double(::std::f64::INFINITY)),
+                Protocol::Resp3,
+                ",inf\r\n"
+            );
+            encodes_to_protocol(
+                Ok(CommandReturn::Double(::std::f64::NEG_INFINITY)),
+                Protocol::Resp3,
+                ",-inf\r\n"
+            );
+        }
+
+        #[test]
+        fn boolean() {
+            encodes_to_protocol(Ok(CommandReturn::Boolean(true)), Protocol::Resp2, ":1\r\n");
+            encodes_to_protocol(Ok(CommandReturn::Boolean(false)), Protocol::Resp2, ":0\r\n");
+            encodes_to_protocol(Ok(CommandReturn::Boolean(true)), Protocol::Resp3, "#t\r\n");
+            encodes_to_protocol(Ok(CommandReturn::Boolean(false)), Protocol::Resp3, "#f\r\n");
+        }
+
+        #[test]
+        fn big_number() {
+            encodes_to_protocol(
+                Ok(CommandReturn::BigNumber(b"12345".to_vec())),
+                Protocol::Resp2,
+                "$5\r\n12345\r\n"
+            );
+            encodes_to_protocol(
+                Ok(CommandReturn::BigNumber(b"12345".to_vec())),
+                Protocol::Resp3,
+                "(12345\r\n"
+            );
+        }
+
+        #[test]
+        fn verbatim_string() {
+            encodes_to_protocol(
+                Ok(CommandReturn::VerbatimString {
+                    format: *b"txt",
+                    data: b"Some string".to_vec(),
+                }),
+                Protocol::Resp2,
+                "$11\r\nSome string\r\n"
+            );
+            encodes_to_protocol(
+                Ok(CommandReturn::VerbatimString {
+                    format: *b"txt",
+                    data: b"Some string".to_vec(),
+                }),
+                Protocol::Resp3,
+                "=15\r\ntxt:Some string\r\n"
+            );
+        }
+
+        #[test]
+        fn map() {
+            let pairs = vec![
+                (CommandReturn::Ok, CommandReturn::Integer(1)),
+            ];
+
+            encodes_to_protocol(
+                Ok(CommandReturn::Map(pairs.clone())),
+                Protocol::Resp2,
+                "*2\r\n+OK\r\n:1\r\n"
+            );
+            encodes_to_protocol(
+                Ok(CommandReturn::Map(pairs)),
+                Protocol::Resp3,
+                "%1\r\n+OK\r\n:1\r\n"
+            );
+        }
+
+        #[test]
+        fn set() {
+            let items = vec![CommandReturn::Integer(1), CommandReturn::Integer(2)];
+
+            encodes_to_protocol(
+                Ok(CommandReturn::Set(items.clone())),
+                Protocol::Resp2,
+                "*2\r\n:1\r\n:2\r\n"
+            );
+            encodes_to_protocol(
+                Ok(CommandReturn::Set(items)),
+                Protocol::Resp3,
+                "~2\r\n:1\r\n:2\r\n"
+            );
+        }
+
+        #[test]
+        fn push() {
+            let items = vec![CommandReturn::BulkString(Cow::Borrowed(&b"message"[..]))];
+
+            encodes_to_protocol(
+                Ok(CommandReturn::Push(items.clone())),
+                Protocol::Resp2,
+                "*1\r\n$7\r\nmessage\r\n"
+            );
+            encodes_to_protocol(
+                Ok(CommandReturn::Push(items)),
+                Protocol::Resp3,
+                ">1\r\n$7\r\nmessage\r\n"
+            );
+        }
+
+        #[test]
+        fn queued() {
+            encodes_to(Ok(CommandReturn::Queued), "+QUEUED\r\n");
+        }
+
+        #[test]
+        fn error_return_inside_an_array() {
+            encodes_to(
+                Ok(CommandReturn::Array(vec![
+                    CommandReturn::Ok,
+                    CommandReturn::Error(CommandError::WrongType),
+                ])),
+                "*2\r\n+OK\r\n-WRONGTYPE Operation against a key holding the wrong kind of value\r\n"
+            );
+        }
+
+        #[test]
+        fn exec_abort() {
+            encodes_to(
+                Err(CommandError::ExecAbort),
+                "-EXECABORT Transaction discarded because of previous errors.\r\n"
+            );
+        }
+
+        #[test]
+        fn exec_without_multi() {
+            encodes_to(Err(CommandError::ExecWithoutMulti), "-ERR EXEC without MULTI\r\n");
+        }
+
+        #[test]
+        fn discard_without_multi() {
+            encodes_to(Err(CommandError::DiscardWithoutMulti), "-ERR DISCARD without MULTI\r\n");
+        }
+
+        #[test]
+        fn nested_multi() {
+            encodes_to(Err(CommandError::NestedMulti), "-ERR MULTI calls can not be nested\r\n");
+        }
+
         fn encodes_to(ret: CommandResult, to: &str) {
+            encodes_to_protocol(ret, Protocol::Resp2, to);
+        }
+
+        fn encodes_to_protocol(ret: CommandResult, protocol: Protocol, to: &str) {
             let mut output = Vec::new();
 
-            assert!(encode(&ret, &mut output).is_ok());
+            assert!(encode(&ret, protocol, &mut output).is_ok());
             assert_eq!(to, String::from_utf8(output).unwrap());
         }
     }
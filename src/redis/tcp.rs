@@ -1,15 +1,18 @@
 use mioco::tcp::{TcpListener, TcpStream};
 use mioco;
-use nom::IResult;
 use redis::commands::Command;
-use redis::database::Database;
-use redis::line::tokenize;
-use redis::resp::{decode_string_array, encode};
-use std::io::{self, Read};
+use redis::database::{CommandError, CommandReturn, Database};
+use redis::line::{tokenize_request, Reason, TokenizeError};
+use redis::resp::{encode, Protocol};
+use redis::transaction::Transaction;
+use std::borrow::Cow;
+use std::io::{self, Read, Write};
 use std::net::SocketAddr;
 use std::str::FromStr;
 use std::sync::{Arc, Mutex};
 
+const BUFFER_SIZE: usize = 1024 * 16;
+
 pub fn listen_async() {
     mioco::start(|| -> io::Result<()> {
         let address = SocketAddr::from_str("127.0.0.1:9876").unwrap();
@@ -28,37 +31,323 @@ pub fn listen_async() {
 }
 
 fn handle_client(mut stream: TcpStream, database: Arc<Mutex<Database>>) -> io::Result<()> {
-    let mut buffer = [0; 1024 * 16];
+    drive(&database, &mut stream)
+}
+
+/// Drives a single connection end-to-end. Bytes accumulate into a growable
+/// buffer and the inner loop drains as many complete commands as it holds
+/// before blocking on another `read` — so commands pipelined into one
+/// packet run back-to-back, and a command split across reads (or a bulk
+/// string too big for one `read`) just waits for the rest instead of being
+/// dropped, the way a fixed single-shot buffer would.
+fn drive<S: Read + Write>(database: &Arc<Mutex<Database>>, stream: &mut S) -> io::Result<()> {
+    let mut buffer = Vec::new();
+    let mut chunk = [0; BUFFER_SIZE];
+    let mut protocol = Protocol::default();
+    // Lives for the whole connection so `WATCH` can run ahead of `MULTI`;
+    // `in_multi` is what actually switches a plain command from running
+    // immediately to being queued on `transaction` instead.
+    let mut transaction = Transaction::new();
+    let mut in_multi = false;
 
     loop {
-        let size = try!(stream.read(&mut buffer[..]));
-
-        let tokenized = match buffer.first() {
-            Some(&b'*') =>
-                match decode_string_array(&buffer[0..size]) {
-                    IResult::Done(_, tokenized) => tokenized,
-                    _ => break
-                },
-            Some(_) =>
-                match tokenize(&buffer[0..size]) {
-                    IResult::Done(_, tokenized) => tokenized,
-                    _ => break
-                },
-            _ =>
-                break
-        };
-
-        match Command::from_slice(&tokenized) {
-            Ok(command) => {
-                let mut database = database.lock().unwrap();
-                let res = database.apply(command);
-                try!(encode(&res, &mut stream));
+        loop {
+            if buffer.is_empty() {
+                break;
             }
-            Err(err) => {
-                try!(encode(&Err(err), &mut stream));
+
+            match tokenize_request(&buffer) {
+                Ok((tokenized, consumed)) => {
+                    let borrowed: Vec<&[u8]> = tokenized.iter().map(Vec::as_slice).collect();
+
+                    match Command::from_slice(&borrowed) {
+                        // HELLO negotiates the wire protocol for this
+                        // connection, so it's intercepted here rather than
+                        // going through `Database::apply`.
+                        Ok(Command::Hello { version }) => {
+                            let res = negotiate_protocol(version).map(|proto| {
+                                protocol = proto;
+                                hello_reply(proto)
+                            });
+                            try!(encode(&res, protocol, stream));
+                        }
+                        Ok(Command::Multi) => {
+                            let res = if in_multi {
+                                Err(CommandError::NestedMulti)
+                            } else {
+                                in_multi = true;
+                                Ok(CommandReturn::Ok)
+                            };
+                            try!(encode(&res, protocol, stream));
+                        }
+                        Ok(Command::Discard) => {
+                            let res = if in_multi {
+                                in_multi = false;
+                                transaction = Transaction::new();
+                                Ok(CommandReturn::Ok)
+                            } else {
+                                Err(CommandError::DiscardWithoutMulti)
+                            };
+                            try!(encode(&res, protocol, stream));
+                        }
+                        Ok(Command::Exec) => {
+                            let res = if in_multi {
+                                in_multi = false;
+                                let mut database = database.lock().unwrap();
+                                transaction.exec(&mut database)
+                            } else {
+                                Err(CommandError::ExecWithoutMulti)
+                            };
+                            try!(encode(&res, protocol, stream));
+                        }
+                        // WATCH's optimistic-locking bookkeeping is
+                        // session-level, so it's recorded on the
+                        // connection's `Transaction` rather than going
+                        // through `Database::apply` directly — though it
+                        // still needs the database locked to snapshot
+                        // each watched key's current value.
+                        Ok(Command::Watch { keys }) => {
+                            let mut database = database.lock().unwrap();
+                            transaction.watch(keys, &mut database);
+                            try!(encode(&Ok(CommandReturn::Ok), protocol, stream));
+                        }
+                        Ok(command) => {
+                            let res = if in_multi {
+                                transaction.push(&borrowed)
+                            } else {
+                                let mut database = database.lock().unwrap();
+                                database.apply(command)
+                            };
+                            try!(encode(&res, protocol, stream));
+                        }
+                        Err(err) => {
+                            let res = if in_multi {
+                                transaction.push(&borrowed)
+                            } else {
+                                Err(err)
+                            };
+                            try!(encode(&res, protocol, stream));
+                        }
+                    }
+
+                    buffer.drain(..consumed);
+                }
+                Err(TokenizeError::Incomplete) =>
+                    break,
+                Err(TokenizeError::Invalid { offset, reason }) => {
+                    try!(write_protocol_error(stream, offset, reason));
+                    return Ok(());
+                }
             }
         }
+
+        match try!(stream.read(&mut chunk)) {
+            0 => break,
+            n => buffer.extend_from_slice(&chunk[..n]),
+        }
     }
 
     Ok(())
 }
+
+fn write_protocol_error<W: Write>(w: &mut W, offset: usize, reason: Reason) -> io::Result<()> {
+    write!(w, "-ERR Protocol error: {} at byte {}\r\n", reason_message(reason), offset)
+}
+
+fn reason_message(reason: Reason) -> &'static str {
+    match reason {
+        Reason::BadHexEscape => "invalid hex escape",
+        Reason::TrailingGarbageAfterQuote => "unbalanced quotes in request",
+        Reason::Malformed => "invalid multibulk length",
+    }
+}
+
+fn negotiate_protocol(version: Option<i64>) -> Result<Protocol, CommandError> {
+    match version {
+        None | Some(2) => Ok(Protocol::Resp2),
+        Some(3) => Ok(Protocol::Resp3),
+        Some(_) => Err(CommandError::UnsupportedProtocol),
+    }
+}
+
+fn hello_reply<'a>(protocol: Protocol) -> CommandReturn<'a> {
+    CommandReturn::Map(vec![
+        (
+            CommandReturn::BulkString(Cow::Borrowed(b"server")),
+            CommandReturn::BulkString(Cow::Borrowed(b"redis")),
+        ),
+        (
+            CommandReturn::BulkString(Cow::Borrowed(b"proto")),
+            CommandReturn::Integer(match protocol {
+                Protocol::Resp2 => 2,
+                Protocol::Resp3 => 3,
+            }),
+        ),
+        (
+            CommandReturn::BulkString(Cow::Borrowed(b"mode")),
+            CommandReturn::BulkString(Cow::Borrowed(b"standalone")),
+        ),
+        (
+            CommandReturn::BulkString(Cow::Borrowed(b"role")),
+            CommandReturn::BulkString(Cow::Borrowed(b"master")),
+        ),
+        (
+            CommandReturn::BulkString(Cow::Borrowed(b"modules")),
+            CommandReturn::Array(vec![]),
+        ),
+    ])
+}
+
+#[cfg(test)]
+mod test {
+    use redis::database::Database;
+    use std::io::{self, Read, Write};
+    use std::sync::{Arc, Mutex};
+    use super::drive;
+
+    #[test]
+    fn pipelined_commands_run_back_to_back() {
+        let database = Arc::new(Mutex::new(Database::new()));
+        let mut conn = FakeConn::new(vec![
+            b"*3\r\n$3\r\nSET\r\n$3\r\nfoo\r\n$3\r\nbar\r\n*2\r\n$3\r\nGET\r\n$3\r\nfoo\r\n".to_vec(),
+        ]);
+
+        assert!(drive(&database, &mut conn).is_ok());
+
+        assert_eq!(b"+OK\r\n$3\r\nbar\r\n".to_vec(), conn.output);
+    }
+
+    #[test]
+    fn command_split_across_reads_is_not_dropped() {
+        let database = Arc::new(Mutex::new(Database::new()));
+        let mut conn = FakeConn::new(vec![
+            b"*3\r\n$3\r\nSET\r\n$3\r\nfo".to_vec(),
+            b"o\r\n$3\r\nbar\r\n".to_vec(),
+        ]);
+
+        assert!(drive(&database, &mut conn).is_ok());
+
+        assert_eq!(b"+OK\r\n".to_vec(), conn.output);
+    }
+
+    #[test]
+    fn byte_at_a_time_input_is_reassembled() {
+        let database = Arc::new(Mutex::new(Database::new()));
+        let input = b"*2\r\n$3\r\nGET\r\n$3\r\nfoo\r\n".to_vec();
+        let chunks = input.iter().map(|&b| vec![b]).collect();
+        let mut conn = FakeConn::new(chunks);
+
+        assert!(drive(&database, &mut conn).is_ok());
+
+        assert_eq!(b"$-1\r\n".to_vec(), conn.output);
+    }
+
+    #[test]
+    fn malformed_input_emits_protocol_error_and_closes() {
+        let database = Arc::new(Mutex::new(Database::new()));
+        let mut conn = FakeConn::new(vec![b"*abc\r\n".to_vec()]);
+
+        assert!(drive(&database, &mut conn).is_ok());
+
+        assert_eq!(
+            b"-ERR Protocol error: invalid multibulk length at byte 1\r\n".to_vec(),
+            conn.output
+        );
+    }
+
+    #[test]
+    fn queued_commands_apply_atomically_on_exec() {
+        let database = Arc::new(Mutex::new(Database::new()));
+        let mut conn = FakeConn::new(vec![
+            b"*1\r\n$5\r\nMULTI\r\n\
+              *3\r\n$3\r\nSET\r\n$3\r\nfoo\r\n$3\r\nbar\r\n\
+              *2\r\n$3\r\nGET\r\n$3\r\nfoo\r\n\
+              *1\r\n$4\r\nEXEC\r\n".to_vec(),
+        ]);
+
+        assert!(drive(&database, &mut conn).is_ok());
+
+        assert_eq!(
+            b"+OK\r\n+QUEUED\r\n+QUEUED\r\n*2\r\n+OK\r\n$3\r\nbar\r\n".to_vec(),
+            conn.output
+        );
+    }
+
+    #[test]
+    fn exec_fails_if_a_watched_key_changed_after_watch() {
+        let database = Arc::new(Mutex::new(Database::new()));
+        let mut conn = FakeConn::new(vec![
+            b"*3\r\n$3\r\nSET\r\n$3\r\nfoo\r\n$3\r\nbar\r\n\
+              *2\r\n$5\r\nWATCH\r\n$3\r\nfoo\r\n\
+              *3\r\n$3\r\nSET\r\n$3\r\nfoo\r\n$7\r\nchanged\r\n\
+              *1\r\n$5\r\nMULTI\r\n\
+              *2\r\n$3\r\nGET\r\n$3\r\nfoo\r\n\
+              *1\r\n$4\r\nEXEC\r\n".to_vec(),
+        ]);
+
+        assert!(drive(&database, &mut conn).is_ok());
+
+        assert_eq!(
+            b"+OK\r\n+OK\r\n+OK\r\n+OK\r\n+QUEUED\r\n$-1\r\n".to_vec(),
+            conn.output
+        );
+    }
+
+    #[test]
+    fn a_queue_time_parse_error_aborts_the_whole_transaction() {
+        let database = Arc::new(Mutex::new(Database::new()));
+        let mut conn = FakeConn::new(vec![
+            b"*1\r\n$5\r\nMULTI\r\n\
+              *3\r\n$3\r\nSET\r\n$3\r\nfoo\r\n$3\r\nbar\r\n\
+              *1\r\n$4\r\nnope\r\n\
+              *1\r\n$4\r\nEXEC\r\n\
+              *2\r\n$3\r\nGET\r\n$3\r\nfoo\r\n".to_vec(),
+        ]);
+
+        assert!(drive(&database, &mut conn).is_ok());
+
+        assert_eq!(
+            b"+OK\r\n+QUEUED\r\n-ERR unknown command 'nope'\r\n\
+              -EXECABORT Transaction discarded because of previous errors.\r\n\
+              $-1\r\n".to_vec(),
+            conn.output
+        );
+    }
+
+    // Hands back one pre-scripted chunk per `read()` call, to exercise a
+    // command whose bytes are split across multiple reads, and records
+    // every `write()` so responses can be asserted on afterwards.
+    struct FakeConn {
+        chunks: Vec<Vec<u8>>,
+        output: Vec<u8>,
+    }
+
+    impl FakeConn {
+        fn new(mut chunks: Vec<Vec<u8>>) -> FakeConn {
+            chunks.reverse();
+            FakeConn { chunks: chunks, output: Vec::new() }
+        }
+    }
+
+    impl Read for FakeConn {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            match self.chunks.pop() {
+                Some(chunk) => {
+                    let len = chunk.len();
+                    buf[..len].copy_from_slice(&chunk);
+                    Ok(len)
+                }
+                None => Ok(0),
+            }
+        }
+    }
+
+    impl Write for FakeConn {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.output.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> { Ok(()) }
+    }
+}
@@ -0,0 +1,278 @@
+use redis::commands::{Bytes, Command};
+use redis::database::{CommandError, CommandResult, CommandReturn, Database};
+use std::mem;
+
+/// Accumulates commands queued between `MULTI` and `EXEC`, mirroring Redis's
+/// "build and queue, then execute atomically" transaction model. Unlike
+/// `Command`, which borrows its arguments from the connection's read
+/// buffer, queued tokens are copied out into owned storage: a transaction
+/// can span several reads, and the buffer gets drained (and its unconsumed
+/// tail shifted) as soon as the next command arrives, which would
+/// invalidate anything still borrowing the bytes of an earlier one.
+#[derive(Debug, Default)]
+pub struct Transaction {
+    commands: Vec<Vec<Vec<u8>>>,
+    watched: Vec<Vec<u8>>,
+    // One `DUMP`-encoded snapshot per watched key, taken at `WATCH` time;
+    // `exec` re-`DUMP`s each key and compares, so it can tell a watched
+    // key changed even though it has no version counter of its own to
+    // check instead.
+    snapshots: Vec<(Vec<u8>, CommandReturn<'static>)>,
+    aborted: bool,
+}
+
+impl Transaction {
+    pub fn new() -> Self { Transaction::default() }
+
+    /// Parses a single command to catch a syntax error while it's still
+    /// cheap to report, then queues its tokens and replies
+    /// `CommandReturn::Queued`. A parse failure marks the transaction
+    /// `aborted` instead of queuing anything, so `exec` can refuse to run
+    /// any of it, matching Redis's `EXECABORT` behavior.
+    pub fn push(&mut self, tokens: &[Bytes]) -> CommandResult<'static> {
+        match Command::from_slice(tokens) {
+            Ok(_) => {
+                self.commands.push(tokens.iter().map(|t| t.to_vec()).collect());
+                Ok(CommandReturn::Queued)
+            }
+            Err(err) => {
+                self.aborted = true;
+                Err(err)
+            }
+        }
+    }
+
+    /// Records keys to watch for optimistic-locking checks, snapshotting
+    /// each one's current value so `exec` can later tell whether any of
+    /// them changed in the meantime.
+    pub fn watch(&mut self, keys: &[Bytes], database: &mut Database) {
+        for key in keys {
+            let snapshot = dump(database, *key);
+            self.watched.push(key.to_vec());
+            self.snapshots.push((key.to_vec(), snapshot));
+        }
+    }
+
+    pub fn watched(&self) -> &[Vec<u8>] { &self.watched }
+
+    pub fn aborted(&self) -> bool { self.aborted }
+
+    /// Runs every queued command against `database` in order, resetting
+    /// the transaction whether it commits or aborts. A command that fails
+    /// at run time (e.g. `WrongType`) still lets the rest of the batch
+    /// apply, matching Redis; its error rides along inside the returned
+    /// `Array` rather than aborting the transaction the way a queue-time
+    /// parse failure does. If any watched key's value no longer matches
+    /// its `WATCH`-time snapshot, the whole batch is skipped and `exec`
+    /// replies `Nil`, matching Redis's optimistic-locking `EXEC` failure.
+    pub fn exec(&mut self, database: &mut Database) -> CommandResult<'static> {
+        if self.aborted {
+            self.take();
+            return Err(CommandError::ExecAbort);
+        }
+
+        let changed = self.snapshots.iter()
+            .any(|&(ref key, ref snapshot)| dump(database, key) != *snapshot);
+
+        if changed {
+            self.take();
+            return Ok(CommandReturn::Nil);
+        }
+
+        let commands = self.take();
+
+        let results = commands.iter()
+            .map(|tokens| {
+                let borrowed: Vec<Bytes> = tokens.iter().map(|t| t.as_slice()).collect();
+                let command = Command::from_slice(&borrowed)
+                    .expect("transaction only ever queues commands that parsed cleanly");
+
+                match database.apply(command) {
+                    Ok(ret) => ret.into_owned(),
+                    Err(err) => CommandReturn::Error(err),
+                }
+            })
+            .collect();
+
+        Ok(CommandReturn::Array(results))
+    }
+
+    /// Returns the queued commands' tokens in order, resetting the
+    /// transaction.
+    fn take(&mut self) -> Vec<Vec<Vec<u8>>> {
+        self.aborted = false;
+        self.watched.clear();
+        self.snapshots.clear();
+        mem::replace(&mut self.commands, Vec::new())
+    }
+}
+
+/// A comparable, owned snapshot of `key`'s current value, independent of
+/// its type — reuses `DUMP`'s encoding rather than inventing a second one
+/// just for `WATCH`. `DUMP` never fails on its own, so this always
+/// succeeds.
+fn dump(database: &mut Database, key: &[u8]) -> CommandReturn<'static> {
+    database.apply(Command::Dump { key: key })
+        .expect("DUMP never fails")
+        .into_owned()
+}
+
+#[cfg(test)]
+mod test {
+    use redis::database::{CommandError, CommandReturn, Database};
+    use redis::commands::{Command, SetOptions};
+    use std::borrow::Cow;
+    use super::Transaction;
+
+    #[test]
+    fn queues_commands_and_replies_queued() {
+        let mut tx = Transaction::new();
+
+        assert_eq!(Ok(CommandReturn::Queued), tx.push(&[b"set", b"foo", b"bar"]));
+        assert_eq!(Ok(CommandReturn::Queued), tx.push(&[b"get", b"foo"]));
+    }
+
+    #[test]
+    fn aborts_on_bad_syntax() {
+        let mut tx = Transaction::new();
+
+        assert_eq!(Ok(CommandReturn::Queued), tx.push(&[b"set", b"foo", b"bar"]));
+        assert_eq!(
+            Err(CommandError::UnknownCommand(b"nope".to_vec())),
+            tx.push(&[b"nope"])
+        );
+
+        assert!(tx.aborted());
+    }
+
+    #[test]
+    fn watch_records_keys() {
+        let mut db = Database::new();
+        let mut tx = Transaction::new();
+
+        tx.watch(&[b"foo", b"bar"], &mut db);
+
+        assert_eq!(&[b"foo".to_vec(), b"bar".to_vec()][..], tx.watched());
+    }
+
+    #[test]
+    fn exec_fails_if_a_watched_key_changed_since_watch() {
+        let mut db = Database::new();
+        let mut tx = Transaction::new();
+
+        db.apply(Command::Set { key: b"foo", value: b"bar", options: SetOptions::default() }).unwrap();
+        tx.watch(&[b"foo"], &mut db);
+
+        db.apply(Command::Set { key: b"foo", value: b"changed", options: SetOptions::default() }).unwrap();
+
+        tx.push(&[b"set", b"foo", b"queued"]).unwrap();
+
+        assert_eq!(Ok(CommandReturn::Nil), tx.exec(&mut db));
+        assert_eq!(
+            Ok(CommandReturn::BulkString(Cow::Borrowed(b"changed"))),
+            db.apply(Command::Get { key: b"foo" })
+        );
+    }
+
+    #[test]
+    fn exec_fails_if_a_watched_key_was_created_since_watch() {
+        let mut db = Database::new();
+        let mut tx = Transaction::new();
+
+        tx.watch(&[b"foo"], &mut db);
+        db.apply(Command::Set { key: b"foo", value: b"bar", options: SetOptions::default() }).unwrap();
+        tx.push(&[b"get", b"foo"]).unwrap();
+
+        assert_eq!(Ok(CommandReturn::Nil), tx.exec(&mut db));
+    }
+
+    #[test]
+    fn exec_succeeds_if_no_watched_key_changed() {
+        let mut db = Database::new();
+        let mut tx = Transaction::new();
+
+        db.apply(Command::Set { key: b"foo", value: b"bar", options: SetOptions::default() }).unwrap();
+        tx.watch(&[b"foo"], &mut db);
+        tx.push(&[b"get", b"foo"]).unwrap();
+
+        assert_eq!(
+            Ok(CommandReturn::Array(vec![CommandReturn::BulkString(Cow::Borrowed(b"bar"))])),
+            tx.exec(&mut db)
+        );
+    }
+
+    #[test]
+    fn exec_resets_watched_keys_even_when_it_fails_the_optimistic_lock() {
+        let mut db = Database::new();
+        let mut tx = Transaction::new();
+
+        db.apply(Command::Set { key: b"foo", value: b"bar", options: SetOptions::default() }).unwrap();
+        tx.watch(&[b"foo"], &mut db);
+        db.apply(Command::Set { key: b"foo", value: b"changed", options: SetOptions::default() }).unwrap();
+
+        tx.exec(&mut db).unwrap();
+
+        assert!(tx.watched().is_empty());
+    }
+
+    #[test]
+    fn exec_applies_queued_commands_in_order() {
+        let mut db = Database::new();
+        let mut tx = Transaction::new();
+
+        tx.push(&[b"set", b"foo", b"bar"]).unwrap();
+        tx.push(&[b"get", b"foo"]).unwrap();
+
+        assert_eq!(
+            Ok(CommandReturn::Array(vec![CommandReturn::Ok, CommandReturn::BulkString(Cow::Borrowed(b"bar"))])),
+            tx.exec(&mut db)
+        );
+    }
+
+    #[test]
+    fn exec_lets_a_wrong_type_error_ride_alongside_other_results() {
+        let mut db = Database::new();
+        db.apply(Command::LPush { key: b"foo", values: vec![b"a"] }).unwrap();
+
+        let mut tx = Transaction::new();
+        tx.push(&[b"get", b"foo"]).unwrap();
+        tx.push(&[b"set", b"bar", b"baz"]).unwrap();
+
+        assert_eq!(
+            Ok(CommandReturn::Array(vec![
+                CommandReturn::Error(CommandError::WrongType),
+                CommandReturn::Ok,
+            ])),
+            tx.exec(&mut db)
+        );
+        assert_eq!(
+            Ok(CommandReturn::BulkString(Cow::Borrowed(b"baz"))),
+            db.apply(Command::Get { key: b"bar" })
+        );
+    }
+
+    #[test]
+    fn exec_aborts_without_mutating_the_database_on_a_queue_time_error() {
+        let mut db = Database::new();
+        let mut tx = Transaction::new();
+
+        tx.push(&[b"set", b"foo", b"bar"]).unwrap();
+        let _ = tx.push(&[b"nope"]);
+
+        assert_eq!(Err(CommandError::ExecAbort), tx.exec(&mut db));
+        assert_eq!(Ok(CommandReturn::Nil), db.apply(Command::Get { key: b"foo" }));
+    }
+
+    #[test]
+    fn exec_resets_state_for_reuse() {
+        let mut db = Database::new();
+        let mut tx = Transaction::new();
+
+        tx.watch(&[b"foo"], &mut db);
+        let _ = tx.push(&[b"nope"]);
+        tx.exec(&mut db).unwrap_err();
+
+        assert!(!tx.aborted());
+        assert!(tx.watched().is_empty());
+    }
+}